@@ -0,0 +1,14 @@
+extern crate victorem;
+
+//Run it and start game servers with GameServerBuilder::master("127.0.0.1:27900").
+fn main() {
+    let port = std::env::args()
+        .nth(1)
+        .and_then(|p| p.parse().ok())
+        .unwrap_or(27900);
+    let mut master = victorem::GameServerBuilder::new(port)
+        .build_master()
+        .unwrap();
+    println!("Master server listening port {}", port);
+    master.run();
+}
@@ -3,7 +3,8 @@ extern crate victorem;
 use std::time::{Duration, Instant};
 
 fn main() {
-    let mut client = victorem::ClientSocket::new(11111, "127.0.0.1:22222").unwrap();
+    let mut client =
+        victorem::ClientSocket::<String, String>::new(11111, "127.0.0.1:22222").unwrap();
     let mut id: u32 = 0;
     let mut timer = Instant::now();
     let period = Duration::from_millis(100);
@@ -11,11 +12,8 @@ fn main() {
         if timer.elapsed() > period {
             timer = Instant::now();
             id += 1;
-            let _ = client.send(format!("Ping {}", id).into_bytes());
+            let _ = client.send(format!("Ping {}", id));
         }
-        let _ = client
-            .recv()
-            .map(|v| String::from_utf8(v).unwrap_or(String::new()))
-            .map(|s| println!("From Server: {}", s));
+        let _ = client.recv().map(|s| println!("From Server: {}", s));
     }
 }
@@ -1,6 +1,5 @@
 extern crate victorem;
 
-use std::net::SocketAddr;
 use std::time::Duration;
 
 struct PingPongGame {
@@ -8,26 +7,26 @@ struct PingPongGame {
 }
 
 impl victorem::Game for PingPongGame {
+    type Command = String;
+    type State = String;
+
     fn handle_command(
         &mut self,
+        _tick: victorem::Tick,
         delta_time: Duration,
-        commands: Vec<Vec<u8>>,
-        from: SocketAddr,
+        commands: Vec<String>,
+        from: victorem::ClientId,
+        _view: victorem::ClientView<String>,
     ) -> victorem::ContinueRunning {
-        for v in commands {
-            println!(
-                "From Client: {:?} {} {}",
-                delta_time,
-                from,
-                String::from_utf8(v).unwrap_or(String::new()),
-            );
+        for command in commands {
+            println!("From Client: {:?} {} {}", delta_time, from, command);
         }
         true
     }
 
-    fn draw(&mut self, delta_time: Duration) -> Vec<u8> {
+    fn draw(&mut self, _tick: victorem::Tick, delta_time: Duration) -> Option<String> {
         self.id += 1;
-        format!("Pong {} {:?}", self.id, delta_time).into_bytes()
+        Some(format!("Pong {} {:?}", self.id, delta_time))
     }
 }
 
@@ -0,0 +1,17 @@
+use super::protocol::ProtocolChecker;
+use super::version::VersionChecker;
+use crate::entities::{Envelope, Exception};
+
+pub fn packet<M>(message: M) -> Envelope<M> {
+    Envelope {
+        protocol_id: ProtocolChecker.get(),
+        protocol_version: VersionChecker.get(),
+        message,
+    }
+}
+
+pub fn open<M>(packet: Envelope<M>) -> Result<M, Exception> {
+    VersionChecker.check(&packet)?;
+    ProtocolChecker.check(&packet)?;
+    Ok(packet.message)
+}
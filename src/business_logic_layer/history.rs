@@ -0,0 +1,64 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+///State of the game saved on some tick.
+#[derive(Debug, Clone)]
+pub struct Snapshot<S> {
+    ///Simulation tick when state was saved.
+    pub tick: u32,
+    ///Time of server [`crate::Clock`] when state was saved.
+    pub time: Duration,
+    ///Saved state.
+    pub state: S,
+}
+
+///Ring buffer with recent states of the game.
+/// Used to rewind world to what client saw for lag compensation.
+pub struct History<S> {
+    snapshots: VecDeque<Snapshot<S>>,
+    capacity: usize,
+}
+
+impl<S> History<S> {
+    pub fn new(capacity: usize) -> History<S> {
+        History {
+            snapshots: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn add(&mut self, tick: u32, time: Duration, state: S) {
+        if self.snapshots.len() >= self.capacity {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(Snapshot {
+            tick,
+            time,
+            state,
+        });
+    }
+
+    ///Returns snapshot saved on this tick if it is still in history.
+    pub fn get(&self, tick: u32) -> Option<&Snapshot<S>> {
+        self.snapshots.iter().find(|s| s.tick == tick)
+    }
+
+    ///Returns last snapshot saved before or at this time.
+    /// None if time is older than whole history.
+    pub fn at(&self, time: Duration) -> Option<&Snapshot<S>> {
+        self.snapshots.iter().rev().find(|s| s.time <= time)
+    }
+
+    ///Returns newest snapshot.
+    pub fn latest(&self) -> Option<&Snapshot<S>> {
+        self.snapshots.back()
+    }
+
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+}
@@ -3,27 +3,18 @@ use std::collections::HashMap;
 
 pub trait IWithId {
     fn get(&self) -> u32;
-    fn set(&mut self, id: u32);
 }
 
-impl IWithId for StatePacket {
+impl<S> IWithId for StatePacket<S> {
     fn get(&self) -> u32 {
         self.id
     }
-
-    fn set(&mut self, id: u32) {
-        self.id = id
-    }
 }
 
-impl IWithId for CommandPacket {
+impl<C> IWithId for CommandPacket<C> {
     fn get(&self) -> u32 {
         self.id
     }
-
-    fn set(&mut self, id: u32) {
-        self.id = id
-    }
 }
 
 pub struct Generator {
@@ -114,9 +105,9 @@ impl<T: IWithId> Arranger<T> {
                 .iter()
                 .min_by(|x, y| x.0.cmp(y.0))
                 .map(|x| *x.0);
-            min_id.map(|x| {
+            if let Some(x) = min_id {
                 self.last_valid_packet_id = if x > 0 { x - 1 } else { 0 };
-            });
+            }
             self.packets = self
                 .packets
                 .drain()
@@ -131,11 +122,18 @@ impl<T: IWithId> Arranger<T> {
     }
 
     fn set_last_valid(&mut self, packets: &[T]) {
-        packets
-            .iter()
-            .map(|p| p.get())
-            .max()
-            .map(|max| self.last_valid_packet_id = max);
+        if let Some(max) = packets.iter().map(|p| p.get()).max() {
+            self.last_valid_packet_id = max;
+        }
+    }
+
+    ///True if some packets wait for lost ones before them.
+    pub fn is_waiting(&self) -> bool {
+        !self.packets.is_empty()
+    }
+
+    pub fn last_received(&self) -> u32 {
+        self.last_received_packet_id
     }
 
     pub fn get_lost(&self) -> (u32, u32) {
@@ -1,36 +1,40 @@
-use crate::entities::{CommandPacket, StatePacket};
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use crate::entities::{CommandPacket, DisconnectPacket, StatePacket};
+use crate::clock::Clock;
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::time::Duration;
 
 pub trait IWithKey {
     fn get(&self) -> Duration;
-    fn set(&mut self, key: Duration);
 }
 
-impl IWithKey for StatePacket {
+impl<S> IWithKey for StatePacket<S> {
     fn get(&self) -> Duration {
         self.session_key
     }
+}
 
-    fn set(&mut self, key: Duration) {
-        self.session_key = key
+impl<C> IWithKey for CommandPacket<C> {
+    fn get(&self) -> Duration {
+        self.session_key
     }
 }
 
-impl IWithKey for CommandPacket {
+impl IWithKey for DisconnectPacket {
     fn get(&self) -> Duration {
         self.session_key
     }
+}
 
-    fn set(&mut self, key: Duration) {
-        self.session_key = key
-    }
+///Time alone is same for sockets created together so it is mixed with random hasher.
+/// Server trusts key to move client to new address so it must be hard to guess.
+pub fn new_key(clock: &dyn Clock) -> Duration {
+    Duration::from_nanos(RandomState::new().hash_one(clock.now()))
 }
 
-pub fn new_key() -> Duration {
-    match SystemTime::now().duration_since(UNIX_EPOCH) {
-        Ok(d) => d,
-        Err(e) => e.duration(),
-    }
+///Secret of session given by server to client to resume it.
+pub fn new_token() -> u64 {
+    RandomState::new().hash_one(0u8)
 }
 
 pub struct Generator {
@@ -38,8 +42,10 @@ pub struct Generator {
 }
 
 impl Generator {
-    pub fn new() -> Generator {
-        Generator { key: new_key() }
+    pub fn new(clock: &dyn Clock) -> Generator {
+        Generator {
+            key: new_key(clock),
+        }
     }
 
     pub fn generate(&self) -> Duration {
@@ -56,7 +62,7 @@ impl Filter {
         Filter { key }
     }
 
-    pub fn is_valid(&self, value: &IWithKey) -> bool {
+    pub fn is_valid(&self, value: &dyn IWithKey) -> bool {
         self.key == value.get()
     }
 }
@@ -0,0 +1,11 @@
+use crate::entities::{ServerFilter, ServerStatus};
+
+pub fn matches(filter: &ServerFilter, status: &ServerStatus) -> bool {
+    filter.version.iter().all(|v| *v == status.version)
+        && !(filter.not_full && status.max_players > 0 && status.players >= status.max_players)
+        && !(filter.not_empty && status.players == 0)
+        && filter
+            .values
+            .iter()
+            .all(|(key, value)| status.values.get(key) == Some(value))
+}
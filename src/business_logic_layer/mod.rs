@@ -8,70 +8,157 @@ pub mod timer;
 
 mod key;
 
+mod rtt;
+
+pub mod envelope;
+
+pub mod registry;
+
+pub mod master;
+
+pub mod query;
+
+pub mod queue;
+
+pub mod history;
+
 use self::id::{Arranger, Filter, Generator};
 use self::key as k;
+pub use self::key::new_token;
 use self::protocol::ProtocolChecker;
+use self::rtt::RttEstimator;
 use self::timer::SleepTimer;
 use self::version::VersionChecker;
+use crate::clock::Clock;
 use crate::data_access_layer::Cache;
-use crate::entities::{CommandPacket, Exception, StatePacket};
+use crate::entities::{CommandPacket, DisconnectPacket, Exception, StatePacket};
+use std::sync::Arc;
+use std::time::Duration;
 
-pub struct Client {
+pub struct Client<C> {
     protocol_version: VersionChecker,
     protocol_id: ProtocolChecker,
     id: Generator,
-    cache: Cache,
+    cache: Cache<C>,
     id_filter: Filter,
     timer: SleepTimer,
     key_generator: k::Generator,
     key_filter: k::Filter,
+    last_state: (u32, Duration),
+    resume_token: Option<u64>,
+    auth: Option<Vec<u8>>,
+    clock: Arc<dyn Clock>,
 }
 
-impl Client {
-    pub fn new() -> Client {
-        let key = k::new_key();
+impl<C: Clone> Client<C> {
+    pub fn new(clock: Arc<dyn Clock>) -> Client<C> {
+        let key = k::new_key(clock.as_ref());
         Client {
             protocol_version: VersionChecker,
             protocol_id: ProtocolChecker,
             id: Generator::new(1),
             cache: Cache::new(),
             id_filter: Filter::new(0),
-            timer: SleepTimer::new(30),
+            timer: SleepTimer::new(30, clock.clone()),
             key_filter: k::Filter::new(key),
-            key_generator: k::Generator::new(),
+            key_generator: k::Generator::new(clock.as_ref()),
+            last_state: (0, clock.now()),
+            resume_token: None,
+            auth: None,
+            clock,
         }
     }
 
-    fn create_command(&mut self, command: Vec<u8>) -> CommandPacket {
+    ///Sends payload with commands until server accepts client with first state.
+    pub fn authenticate(&mut self, payload: Vec<u8>) {
+        self.auth = Some(payload);
+    }
+
+    ///Asks server to resume its session with token instead of creating new one.
+    pub fn resume(&mut self, token: u64) {
+        self.resume_token = Some(token);
+    }
+
+    pub fn resume_token(&self) -> Option<u64> {
+        self.resume_token
+    }
+
+    fn create_command(&mut self, command: C) -> CommandPacket<C> {
         CommandPacket {
             protocol_id: self.protocol_id.get(),
             protocol_version: self.protocol_version.get(),
             id: self.id.generate(),
             command,
             session_key: self.key_generator.generate(),
+            last_state: self.last_state.0,
+            ack_delay: self.clock.now() - self.last_state.1,
+            resume_token: self.resume_token,
+            auth: self.auth.clone(),
         }
     }
 
-    pub fn send(&mut self, command: Vec<u8>) -> CommandPacket {
+    fn acknowledge(&self, mut command: CommandPacket<C>) -> CommandPacket<C> {
+        command.last_state = self.last_state.0;
+        command.ack_delay = self.clock.now() - self.last_state.1;
+        command
+    }
+
+    pub fn send(&mut self, command: C) -> CommandPacket<C> {
+        //Sleeps before creating command so ack delay don't include time of sleep.
+        self.timer.sleep();
         let command = self.create_command(command);
         self.cache.add(command.clone());
-        self.timer.sleep();
         command
     }
 
-    pub fn recv(&mut self, state: StatePacket) -> Result<(Vec<u8>, Vec<CommandPacket>), Exception> {
+    pub fn recv<S>(
+        &mut self,
+        state: StatePacket<S>,
+    ) -> Result<(S, Vec<CommandPacket<C>>), Exception> {
         self.protocol_version.check(&state)?;
         self.protocol_id.check(&state)?;
         if !self.key_filter.is_valid(&state) {
+            debug!("new session of server");
             self.key_filter = k::Filter::new(state.session_key);
             self.id_filter = Filter::new(0);
         }
-        self.id_filter.filter(&state)?;
+        if let Err(e) = self.id_filter.filter(&state) {
+            debug!(id = state.id, "not ordered state dropped");
+            return Err(e);
+        }
+        self.last_state = (state.id, self.clock.now());
+        self.resume_token = Some(state.resume_token);
+        self.auth = None;
         let vec = self.get_lost(state.last_received, state.sequence);
         Ok((state.state, vec))
     }
 
-    fn get_lost(&mut self, max_id: u32, sequence: u32) -> Vec<CommandPacket> {
+    pub fn disconnect(&self, reason: &str) -> DisconnectPacket {
+        DisconnectPacket {
+            protocol_id: self.protocol_id.get(),
+            protocol_version: self.protocol_version.get(),
+            session_key: self.key_generator.generate(),
+            reason: reason.to_owned(),
+        }
+    }
+
+    ///Returns reason of disconnect if packet is from current session of server.
+    pub fn recv_disconnect(&mut self, packet: DisconnectPacket) -> Result<String, Exception> {
+        self.protocol_id.check(&packet)?;
+        //Without any state there is no session to check so trusts packet.
+        // Server of other version rejects client with it too.
+        if self.last_state.0 == 0 {
+            return Ok(packet.reason);
+        }
+        self.protocol_version.check(&packet)?;
+        if !self.key_filter.is_valid(&packet) {
+            debug!("disconnect not from current session dropped");
+            return Err(Exception::NotValidIdError);
+        }
+        Ok(packet.reason)
+    }
+
+    fn get_lost(&mut self, max_id: u32, sequence: u32) -> Vec<CommandPacket<C>> {
         let mut x = max_id;
         let mut y = 0;
         let mut ids = Vec::<u32>::new();
@@ -88,69 +175,167 @@ impl Client {
                 ids.push(x);
             }
         }
-        self.cache.get_range(&ids)
+        let lost: Vec<_> = self
+            .cache
+            .get_range(&ids)
+            .into_iter()
+            .map(|c| self.acknowledge(c))
+            .collect();
+        if !lost.is_empty() {
+            debug!(count = lost.len(), "resending lost commands");
+        }
+        lost
+    }
+}
+
+///Disconnect for client without session like client of other protocol version.
+pub fn reject(reason: &str) -> DisconnectPacket {
+    DisconnectPacket {
+        protocol_id: ProtocolChecker.get(),
+        protocol_version: VersionChecker.get(),
+        session_key: Duration::new(0, 0),
+        reason: reason.to_owned(),
     }
 }
 
-pub struct Server {
+pub fn check_version<C>(command: &CommandPacket<C>) -> Result<(), Exception> {
+    VersionChecker.check(command)
+}
+
+pub struct Server<C> {
     protocol_version: VersionChecker,
     protocol_id: ProtocolChecker,
     id: Generator,
-    arranger: Arranger<CommandPacket>,
+    arranger: Arranger<CommandPacket<C>>,
     key_generator: k::Generator,
     key_filter: k::Filter,
+    rtt: RttEstimator,
+    out_of_order: u64,
+    resume_token: u64,
+    last_seen: Duration,
+    clock: Arc<dyn Clock>,
 }
 
-impl Server {
-    pub fn new() -> Server {
-        let key = k::new_key();
+impl<C> Server<C> {
+    pub fn new(clock: Arc<dyn Clock>, resume_token: u64) -> Server<C> {
+        let key = k::new_key(clock.as_ref());
         Server {
             protocol_version: VersionChecker,
             protocol_id: ProtocolChecker,
             id: Generator::new(1),
             arranger: Arranger::new(0),
             key_filter: k::Filter::new(key),
-            key_generator: k::Generator::new(),
+            key_generator: k::Generator::new(clock.as_ref()),
+            rtt: RttEstimator::new(clock.clone()),
+            out_of_order: 0,
+            resume_token,
+            last_seen: clock.now(),
+            clock,
         }
     }
 
-    pub fn send(&mut self, state: Vec<u8>) -> StatePacket {
+    pub fn send<S>(&mut self, state: S, tick: u32) -> StatePacket<S> {
         let (sequence, last_id) = self.arranger.get_lost();
+        let id = self.id.generate();
+        self.rtt.on_send(id);
         StatePacket {
             protocol_id: self.protocol_id.get(),
             protocol_version: self.protocol_version.get(),
-            id: self.id.generate(),
-            state: state,
+            id,
+            state,
             last_received: last_id,
-            sequence: sequence,
+            sequence,
+            tick,
             session_key: self.key_generator.generate(),
+            resume_token: self.resume_token,
         }
     }
 
-    pub fn recv(&mut self, command: CommandPacket) -> Result<Vec<Vec<u8>>, Exception> {
+    pub fn disconnect(&self, reason: &str) -> DisconnectPacket {
+        DisconnectPacket {
+            protocol_id: self.protocol_id.get(),
+            protocol_version: self.protocol_version.get(),
+            session_key: self.key_generator.generate(),
+            reason: reason.to_owned(),
+        }
+    }
+
+    ///Returns reason of disconnect if packet is from current session of client.
+    pub fn recv_disconnect(&mut self, packet: DisconnectPacket) -> Result<String, Exception> {
+        self.protocol_version.check(&packet)?;
+        self.protocol_id.check(&packet)?;
+        if !self.key_filter.is_valid(&packet) {
+            debug!("disconnect not from current session dropped");
+            return Err(Exception::NotValidIdError);
+        }
+        Ok(packet.reason)
+    }
+
+    pub fn recv(&mut self, command: CommandPacket<C>) -> Result<Vec<C>, Exception> {
         self.protocol_version.check(&command)?;
         self.protocol_id.check(&command)?;
         if !self.key_filter.is_valid(&command) {
+            debug!("new session of client");
             self.key_filter = k::Filter::new(command.session_key);
             self.arranger = Arranger::new(0);
         }
-        self.arranger.add(command)?;
+        self.last_seen = self.clock.now();
+        self.rtt.on_ack(command.last_state, command.ack_delay);
+        //Command older than last received fills gap so it was resent or reordered.
+        let id = command.id;
+        let is_out_of_order = id < self.arranger.last_received();
+        if let Err(e) = self.arranger.add(command) {
+            debug!(id, "not ordered command dropped");
+            return Err(e);
+        }
+        if is_out_of_order {
+            trace!(id, "out of order command received");
+            self.out_of_order += 1;
+        }
         let vec = self.arranger.arrange();
         Ok(vec.into_iter().map(|v| v.command).collect())
     }
+
+    pub fn rtt(&self) -> Duration {
+        self.rtt.rtt()
+    }
+
+    ///True if command is from current session of client or has its resume token.
+    pub fn is_session(&self, command: &CommandPacket<C>) -> bool {
+        self.key_filter.is_valid(command) || command.resume_token == Some(self.resume_token)
+    }
+
+    ///Time since last command from client.
+    pub fn idle(&self) -> Duration {
+        self.clock.now() - self.last_seen
+    }
+
+    ///True if some commands wait for lost ones client must resend.
+    pub fn is_waiting(&self) -> bool {
+        self.arranger.is_waiting()
+    }
+
+    ///Number of accepted commands received after newer ones.
+    pub fn out_of_order(&self) -> u64 {
+        self.out_of_order
+    }
 }
 
 #[cfg(test)]
 mod bll_test {
     use crate::business_logic_layer::{Client, Server};
+    use crate::clock::{Clock, ManualClock};
+    use std::sync::Arc;
+    use std::time::Duration;
 
     #[test]
     fn client_should_recv_ordered_packets() {
-        let mut client = Client::new();
-        let mut server = Server::new();
-        let packet1 = server.send(vec![1]);
-        let packet2 = server.send(vec![2]);
-        let packet3 = server.send(vec![3]);
+        let clock: Arc<dyn Clock> = Arc::new(ManualClock::default());
+        let mut client = Client::<Vec<u8>>::new(clock.clone());
+        let mut server = Server::<Vec<u8>>::new(clock.clone(), 1);
+        let packet1 = server.send(vec![1], 1);
+        let packet2 = server.send(vec![2], 2);
+        let packet3 = server.send(vec![3], 3);
         let r_packet1 = client.recv(packet1);
         let r_packet3 = client.recv(packet3);
         let r_packet2 = client.recv(packet2);
@@ -168,20 +353,19 @@ mod bll_test {
             }
         });
 
-        assert!({
-            match r_packet2 {
-                Err(crate::entities::Exception::NotOrderedPacketError) => true,
-                _ => false,
-            }
-        });
+        assert!(matches!(
+            r_packet2,
+            Err(crate::entities::Exception::NotOrderedPacketError)
+        ));
     }
 
     #[test]
     fn server_should_recv_ordered_and_reliable_packets() {
-        let mut client = Client::new();
-        let mut server = Server::new();
+        let clock: Arc<dyn Clock> = Arc::new(ManualClock::default());
+        let mut client = Client::<Vec<u8>>::new(clock.clone());
+        let mut server = Server::<Vec<u8>>::new(clock.clone(), 1);
         let command1 = client.send(vec![1]);
-        let command2 = client.send(vec![2]);
+        let _command2 = client.send(vec![2]);
         let command3 = client.send(vec![3]);
         assert_eq!(3, command3.id);
         let r1 = server.recv(command1);
@@ -198,12 +382,13 @@ mod bll_test {
                 Ok(v) => v.is_empty(),
             }
         });
-        let state1 = server.send(vec![4]);
+        let state1 = server.send(vec![4], 4);
         assert_eq!(3, state1.last_received);
         assert_eq!(1, state1.sequence);
-        let (b, commands) = client.recv(state1).unwrap();
+        let (_, commands) = client.recv(state1).unwrap();
 
         let r2 = server.recv(commands.first().unwrap().to_owned());
+        assert_eq!(1, server.out_of_order());
         assert!({
             match r2 {
                 Err(_) => false,
@@ -214,12 +399,13 @@ mod bll_test {
 
     #[test]
     fn client_should_resend_undelivered_packets() {
-        let mut client = Client::new();
-        let mut server = Server::new();
+        let clock: Arc<dyn Clock> = Arc::new(ManualClock::default());
+        let mut client = Client::<Vec<u8>>::new(clock.clone());
+        let mut server = Server::<Vec<u8>>::new(clock.clone(), 1);
         let command1 = client.send(vec![1]);
-        let command2 = client.send(vec![2]);
-        let r1 = server.recv(command1);
-        let state1 = server.send(vec![4]);
+        let _command2 = client.send(vec![2]);
+        let _r1 = server.recv(command1);
+        let state1 = server.send(vec![4], 4);
         assert_eq!(state1.last_received, 1);
         let (b, commands) = client.recv(state1).unwrap();
         assert_eq!(1, commands.len());
@@ -228,4 +414,200 @@ mod bll_test {
         assert_eq!(cmd.id, 2);
         assert_eq!(cmd.command, vec![2]);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn server_should_estimate_rtt_from_acknowledged_state() {
+        let manual = ManualClock::default();
+        let clock: Arc<dyn Clock> = Arc::new(manual.clone());
+        let mut client = Client::<Vec<u8>>::new(clock.clone());
+        let mut server = Server::<Vec<u8>>::new(clock.clone(), 1);
+        let state = server.send(vec![1], 1);
+        manual.advance(Duration::from_millis(20));
+        client.recv(state).unwrap();
+        let command = client.send(vec![2]);
+        assert_eq!(1, command.last_state);
+        assert_eq!(Duration::from_millis(10), command.ack_delay);
+        server.recv(command).unwrap();
+        assert_eq!(Duration::from_millis(20), server.rtt());
+    }
+
+    #[test]
+    fn client_should_accept_disconnect_only_from_its_server() {
+        let manual = ManualClock::default();
+        let clock: Arc<dyn Clock> = Arc::new(manual.clone());
+        let mut client = Client::<Vec<u8>>::new(clock.clone());
+        let server = Server::<Vec<u8>>::new(clock.clone(), 1);
+        manual.advance(Duration::from_millis(1));
+        let mut other = Server::<Vec<u8>>::new(clock.clone(), 1);
+        client.recv(other.send(vec![1], 1)).unwrap();
+        assert!(client.recv_disconnect(server.disconnect("bye")).is_err());
+        assert_eq!("bye", client.recv_disconnect(other.disconnect("bye")).unwrap());
+    }
+
+    #[test]
+    fn server_should_accept_disconnect_only_from_its_client() {
+        let manual = ManualClock::default();
+        let clock: Arc<dyn Clock> = Arc::new(manual.clone());
+        let client = Client::<Vec<u8>>::new(clock.clone());
+        manual.advance(Duration::from_millis(1));
+        let mut other = Client::<Vec<u8>>::new(clock.clone());
+        let mut server = Server::<Vec<u8>>::new(clock.clone(), 1);
+        server.recv(other.send(vec![1])).unwrap();
+        assert!(server.recv_disconnect(client.disconnect("bye")).is_err());
+        assert_eq!("bye", server.recv_disconnect(other.disconnect("bye")).unwrap());
+    }
+
+    #[test]
+    fn client_should_accept_rejection_of_other_version_only_before_session() {
+        let clock: Arc<dyn Clock> = Arc::new(ManualClock::default());
+        let mut client = Client::<Vec<u8>>::new(clock.clone());
+        let mut server = Server::<Vec<u8>>::new(clock.clone(), 1);
+        let mut rejection = crate::business_logic_layer::reject("Bad version");
+        rejection.protocol_version += 1;
+        assert_eq!("Bad version", client.recv_disconnect(rejection.clone()).unwrap());
+        client.recv(server.send(vec![1], 1)).unwrap();
+        assert!(client.recv_disconnect(rejection).is_err());
+    }
+
+    #[test]
+    fn server_should_recognize_session_of_its_client_only() {
+        let clock: Arc<dyn Clock> = Arc::new(ManualClock::default());
+        let mut client = Client::<Vec<u8>>::new(clock.clone());
+        let mut other = Client::<Vec<u8>>::new(clock.clone());
+        let mut server = Server::<Vec<u8>>::new(clock.clone(), 1);
+        server.recv(client.send(vec![1])).unwrap();
+        assert!(server.is_session(&client.send(vec![2])));
+        assert!(!server.is_session(&other.send(vec![3])));
+    }
+
+    #[test]
+    fn server_should_recognize_client_with_its_resume_token() {
+        let clock: Arc<dyn Clock> = Arc::new(ManualClock::default());
+        let mut client = Client::<Vec<u8>>::new(clock.clone());
+        let mut server = Server::<Vec<u8>>::new(clock.clone(), 7);
+        server.recv(client.send(vec![1])).unwrap();
+        client.recv(server.send(vec![2], 1)).unwrap();
+        assert_eq!(Some(7), client.resume_token());
+        let mut resumed = Client::<Vec<u8>>::new(clock.clone());
+        assert!(!server.is_session(&resumed.send(vec![3])));
+        resumed.resume(7);
+        assert!(server.is_session(&resumed.send(vec![4])));
+    }
+
+    #[test]
+    fn registry_should_forget_servers_not_registered_again() {
+        let manual = ManualClock::default();
+        let mut registry =
+            crate::business_logic_layer::registry::Registry::new(Duration::from_millis(100), Arc::new(manual.clone()));
+        let address: std::net::SocketAddr = "10.0.0.1:1000".parse().unwrap();
+        registry.register("home".to_owned(), address);
+        manual.advance(Duration::from_millis(100));
+        assert_eq!(Some(address), registry.find("home"));
+        assert_eq!(None, registry.find("work"));
+        manual.advance(Duration::from_millis(1));
+        assert_eq!(None, registry.find("home"));
+        registry.expire();
+        assert!(registry.entries().is_empty());
+    }
+
+    #[test]
+    fn rate_limiter_should_limit_requests_of_address_in_period() {
+        let manual = ManualClock::default();
+        let mut limiter = crate::business_logic_layer::query::RateLimiter::new(
+            2,
+            Duration::from_millis(100),
+            Arc::new(manual.clone()),
+        );
+        let first = "10.0.0.1".parse().unwrap();
+        let second = "10.0.0.2".parse().unwrap();
+        assert!(limiter.allow(first));
+        assert!(limiter.allow(first));
+        assert!(!limiter.allow(first));
+        assert!(limiter.allow(second));
+        manual.advance(Duration::from_millis(100));
+        limiter.expire();
+        assert!(limiter.allow(first));
+    }
+
+    #[test]
+    fn wait_queue_should_admit_clients_in_order_they_came() {
+        use crate::business_logic_layer::queue::{Admission, WaitQueue};
+        let manual = ManualClock::default();
+        let mut queue = WaitQueue::new(2, Duration::from_millis(100), Arc::new(manual.clone()));
+        assert_eq!(Admission::Admitted, queue.admit(1, true));
+        assert_eq!(Admission::Queued, queue.admit(2, false));
+        assert_eq!(Admission::Queued, queue.admit(3, false));
+        assert_eq!(Admission::Full, queue.admit(4, false));
+        assert_eq!(Admission::Waiting(1), queue.admit(3, true));
+        assert_eq!(Admission::Admitted, queue.admit(2, true));
+        assert_eq!(Admission::Queued, queue.admit(4, true));
+        manual.advance(Duration::from_millis(101));
+        assert_eq!(Admission::Waiting(1), queue.admit(4, false));
+        queue.expire();
+        assert_eq!(Admission::Admitted, queue.admit(4, true));
+    }
+
+    #[test]
+    fn filter_should_match_servers_with_all_its_conditions() {
+        use crate::business_logic_layer::master::matches;
+        use crate::entities::{ServerFilter, ServerStatus};
+        let mut status = ServerStatus {
+            players: 2,
+            max_players: 2,
+            version: "1.0".to_owned(),
+            ..Default::default()
+        };
+        status.values.insert("map".to_owned(), "de_dust".to_owned());
+        let mut filter = ServerFilter::default();
+        assert!(matches(&filter, &status));
+        filter.not_empty = true;
+        filter.version = Some("1.0".to_owned());
+        filter.values.insert("map".to_owned(), "de_dust".to_owned());
+        assert!(matches(&filter, &status));
+        filter.not_full = true;
+        assert!(!matches(&filter, &status));
+        status.max_players = 0;
+        assert!(matches(&filter, &status));
+        filter.values.insert("mode".to_owned(), "ctf".to_owned());
+        assert!(!matches(&filter, &status));
+    }
+
+    #[test]
+    fn history_should_keep_only_recent_snapshots() {
+        let mut history = crate::business_logic_layer::history::History::new(2);
+        history.add(1, Duration::from_millis(10), vec![1]);
+        history.add(2, Duration::from_millis(20), vec![2]);
+        let middle = Duration::from_millis(15);
+        assert_eq!(Some(vec![1]), history.at(middle).map(|s| s.state.clone()));
+        history.add(3, Duration::from_millis(30), vec![3]);
+        assert!(history.get(1).is_none());
+        assert_eq!(2, history.len());
+        assert_eq!(Some(3), history.latest().map(|s| s.tick));
+        assert!(history.at(middle).is_none());
+        assert_eq!(Some(2), history.at(Duration::from_millis(25)).map(|s| s.tick));
+    }
+
+    #[test]
+    fn step_timer_should_not_catch_up_more_than_max_steps() {
+        let manual = ManualClock::default();
+        let mut timer =
+            crate::business_logic_layer::timer::StepTimer::new(5, 3, Arc::new(manual.clone()));
+        manual.advance(Duration::from_millis(52));
+        assert_eq!(3, timer.steps());
+        assert_eq!(0, timer.steps());
+        manual.advance(Duration::from_millis(3));
+        assert_eq!(1, timer.steps());
+    }
+
+    #[test]
+    fn wait_timer_should_continue_only_after_period() {
+        let manual = ManualClock::default();
+        let mut timer =
+            crate::business_logic_layer::timer::WaitTimer::new(30, Arc::new(manual.clone()));
+        manual.advance(Duration::from_millis(30));
+        assert!(!timer.continue_execution());
+        manual.advance(Duration::from_millis(1));
+        assert!(timer.continue_execution());
+        assert!(!timer.continue_execution());
+    }
+}
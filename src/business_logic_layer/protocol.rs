@@ -1,29 +1,32 @@
-use crate::entities::{CommandPacket, Exception, StatePacket};
+use crate::entities::{CommandPacket, DisconnectPacket, Envelope, Exception, StatePacket};
 
 const PROTOCOL_ID: u8 = 8;
 
 pub trait IWithProtocol {
     fn get(&self) -> u8;
-    fn set(&mut self, id: u8);
 }
 
-impl IWithProtocol for StatePacket {
+impl<S> IWithProtocol for StatePacket<S> {
     fn get(&self) -> u8 {
         self.protocol_id
     }
+}
 
-    fn set(&mut self, id: u8) {
-        self.protocol_id = id
+impl<C> IWithProtocol for CommandPacket<C> {
+    fn get(&self) -> u8 {
+        self.protocol_id
     }
 }
 
-impl IWithProtocol for CommandPacket {
+impl IWithProtocol for DisconnectPacket {
     fn get(&self) -> u8 {
         self.protocol_id
     }
+}
 
-    fn set(&mut self, id: u8) {
-        self.protocol_id = id
+impl<M> IWithProtocol for Envelope<M> {
+    fn get(&self) -> u8 {
+        self.protocol_id
     }
 }
 
@@ -0,0 +1,45 @@
+use crate::clock::Clock;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+///Limits answers to requests without session so server can't be used to flood others.
+/// Counts requests of each IP address in period.
+pub struct RateLimiter {
+    requests: HashMap<IpAddr, (Duration, u32)>,
+    limit: u32,
+    period: Duration,
+    clock: Arc<dyn Clock>,
+}
+
+impl RateLimiter {
+    pub fn new(limit: u32, period: Duration, clock: Arc<dyn Clock>) -> RateLimiter {
+        RateLimiter {
+            requests: HashMap::new(),
+            limit,
+            period,
+            clock,
+        }
+    }
+
+    ///Returns false if address sent too many requests in this period.
+    pub fn allow(&mut self, address: IpAddr) -> bool {
+        let now = self.clock.now();
+        let period = self.period;
+        let (start, count) = self.requests.entry(address).or_insert((now, 0));
+        if now - *start >= period {
+            *start = now;
+            *count = 0;
+        }
+        *count += 1;
+        *count <= self.limit
+    }
+
+    ///Forgets addresses with ended period.
+    pub fn expire(&mut self) {
+        let now = self.clock.now();
+        let period = self.period;
+        self.requests.retain(|_, (start, _)| now - *start < period);
+    }
+}
@@ -0,0 +1,65 @@
+use crate::clock::Clock;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Duration;
+
+///What server does with new client when it has limit of clients.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Admission {
+    ///Client gets session.
+    Admitted,
+    ///Client waits on this place in queue. Zero is first.
+    Waiting(usize),
+    ///Client is put to end of queue.
+    Queued,
+    ///There is no free place and queue is full.
+    Full,
+}
+
+///Clients waiting for free place on full server in order they came.
+/// Client not asking again for timeout loses its place.
+pub struct WaitQueue<K> {
+    clients: VecDeque<(K, Duration)>,
+    size: usize,
+    timeout: Duration,
+    clock: Arc<dyn Clock>,
+}
+
+impl<K: PartialEq> WaitQueue<K> {
+    pub fn new(size: usize, timeout: Duration, clock: Arc<dyn Clock>) -> WaitQueue<K> {
+        WaitQueue {
+            clients: VecDeque::new(),
+            size,
+            timeout,
+            clock,
+        }
+    }
+
+    ///Client is admitted only if there is free place and nobody waits before it.
+    pub fn admit(&mut self, client: K, is_free: bool) -> Admission {
+        let now = self.clock.now();
+        match self.clients.iter().position(|(c, _)| *c == client) {
+            Some(0) if is_free => {
+                self.clients.pop_front();
+                Admission::Admitted
+            }
+            Some(place) => {
+                self.clients[place].1 = now;
+                Admission::Waiting(place)
+            }
+            None if is_free && self.clients.is_empty() => Admission::Admitted,
+            None if self.clients.len() < self.size => {
+                self.clients.push_back((client, now));
+                Admission::Queued
+            }
+            None => Admission::Full,
+        }
+    }
+
+    ///Removes clients not asking again for timeout.
+    pub fn expire(&mut self) {
+        let now = self.clock.now();
+        let timeout = self.timeout;
+        self.clients.retain(|(_, at)| now - *at <= timeout);
+    }
+}
@@ -0,0 +1,57 @@
+use crate::clock::Clock;
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Arc;
+use std::time::Duration;
+
+///Game servers registered with time of last registration.
+/// Rendezvous keeps address by name and master server keeps status by address.
+pub struct Registry<K, V> {
+    servers: HashMap<K, (V, Duration)>,
+    timeout: Duration,
+    clock: Arc<dyn Clock>,
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> Registry<K, V> {
+    pub fn new(timeout: Duration, clock: Arc<dyn Clock>) -> Registry<K, V> {
+        Registry {
+            servers: HashMap::new(),
+            timeout,
+            clock,
+        }
+    }
+
+    pub fn register(&mut self, key: K, value: V) {
+        self.servers.insert(key, (value, self.clock.now()));
+    }
+
+    pub fn find<Q: Hash + Eq + ?Sized>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+    {
+        self.servers
+            .get(key)
+            .filter(|(_, at)| !self.is_expired(*at))
+            .map(|(value, _)| value.clone())
+    }
+
+    ///Removes servers not registered again for timeout.
+    pub fn expire(&mut self) {
+        let now = self.clock.now();
+        let timeout = self.timeout;
+        self.servers.retain(|_, (_, at)| now - *at <= timeout);
+    }
+
+    pub fn entries(&self) -> Vec<(K, V)> {
+        self.servers
+            .iter()
+            .filter(|(_, (_, at))| !self.is_expired(*at))
+            .map(|(key, (value, _))| (key.clone(), value.clone()))
+            .collect()
+    }
+
+    fn is_expired(&self, at: Duration) -> bool {
+        self.clock.now() - at > self.timeout
+    }
+}
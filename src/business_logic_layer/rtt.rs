@@ -0,0 +1,56 @@
+use crate::clock::Clock;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Duration;
+
+const MAX_PENDING: usize = 64;
+
+pub struct RttEstimator {
+    sent: VecDeque<(u32, Duration)>,
+    last_acked: u32,
+    rtt: Option<Duration>,
+    clock: Arc<dyn Clock>,
+}
+
+impl RttEstimator {
+    pub fn new(clock: Arc<dyn Clock>) -> RttEstimator {
+        RttEstimator {
+            sent: VecDeque::new(),
+            last_acked: 0,
+            rtt: None,
+            clock,
+        }
+    }
+
+    pub fn on_send(&mut self, id: u32) {
+        if self.sent.len() >= MAX_PENDING {
+            self.sent.pop_front();
+        }
+        self.sent.push_back((id, self.clock.now()));
+    }
+
+    pub fn on_ack(&mut self, id: u32, ack_delay: Duration) {
+        if id <= self.last_acked {
+            return;
+        }
+        self.last_acked = id;
+        let sent_at = match self.sent.iter().find(|(i, _)| *i == id) {
+            Some((_, time)) => *time,
+            None => return,
+        };
+        let sample = (self.clock.now() - sent_at)
+            .checked_sub(ack_delay)
+            .unwrap_or_else(|| Duration::new(0, 0));
+        self.rtt = Some(match self.rtt {
+            Some(rtt) => rtt * 7 / 8 + sample / 8,
+            None => sample,
+        });
+        while self.sent.front().is_some_and(|(i, _)| *i <= id) {
+            self.sent.pop_front();
+        }
+    }
+
+    pub fn rtt(&self) -> Duration {
+        self.rtt.unwrap_or_else(|| Duration::new(0, 0))
+    }
+}
@@ -1,50 +1,51 @@
-use std::thread;
-use std::time::{Duration, Instant};
+use crate::clock::Clock;
+use std::sync::Arc;
+use std::time::Duration;
 
 pub struct SleepTimer {
     time: Duration,
-    instant: Instant,
+    instant: Duration,
+    clock: Arc<dyn Clock>,
 }
 
 impl SleepTimer {
-    pub fn new(sleep_in_millis: u64) -> SleepTimer {
+    pub fn new(sleep_in_millis: u64, clock: Arc<dyn Clock>) -> SleepTimer {
         SleepTimer {
             time: Duration::from_millis(sleep_in_millis),
-            instant: Instant::now(),
+            instant: clock.now(),
+            clock,
         }
     }
 
     pub fn sleep(&mut self) {
-        let elapsed = self.instant.elapsed();
-        self.time
-            .checked_sub(elapsed)
-            .and_then(|d| {
-                if d == Duration::new(0, 0) {
-                    None
-                } else {
-                    Some(d)
-                }
-            })
-            .map(thread::sleep);
-        self.instant = Instant::now();
+        let elapsed = self.clock.now() - self.instant;
+        if let Some(d) = self.time.checked_sub(elapsed) {
+            if d != Duration::new(0, 0) {
+                self.clock.sleep(d);
+            }
+        }
+        self.instant = self.clock.now();
     }
 }
 
 pub struct WaitTimer {
     time: Duration,
-    instant: Instant,
+    instant: Duration,
+    clock: Arc<dyn Clock>,
 }
 
 impl WaitTimer {
-    pub fn new(millis: u64) -> WaitTimer {
+    pub fn new(millis: u64, clock: Arc<dyn Clock>) -> WaitTimer {
         WaitTimer {
             time: Duration::from_millis(millis),
-            instant: Instant::now(),
+            instant: clock.now(),
+            clock,
         }
     }
     pub fn continue_execution(&mut self) -> bool {
-        if self.instant.elapsed() > self.time {
-            self.instant = Instant::now();
+        let now = self.clock.now();
+        if now - self.instant > self.time {
+            self.instant = now;
             true
         } else {
             false
@@ -53,21 +54,56 @@ impl WaitTimer {
 }
 
 pub struct ElapsedTimer {
-    time: Duration,
-    instant: Instant,
+    instant: Duration,
+    clock: Arc<dyn Clock>,
 }
 
 impl ElapsedTimer {
-    pub fn new() -> ElapsedTimer {
+    pub fn new(clock: Arc<dyn Clock>) -> ElapsedTimer {
         ElapsedTimer {
-            time: Duration::new(0, 0),
-            instant: Instant::now(),
+            instant: clock.now(),
+            clock,
         }
     }
     pub fn elapsed(&mut self) -> Duration {
-        let elapsed = self.instant.elapsed();
-        let res = elapsed - self.time;
-        self.time = elapsed;
+        let now = self.clock.now();
+        let res = now - self.instant;
+        self.instant = now;
         res
     }
 }
+
+pub struct StepTimer {
+    step: Duration,
+    max_steps: u32,
+    accumulator: Duration,
+    instant: Duration,
+    clock: Arc<dyn Clock>,
+}
+
+impl StepTimer {
+    pub fn new(step_in_millis: u64, max_steps: u32, clock: Arc<dyn Clock>) -> StepTimer {
+        StepTimer {
+            step: Duration::from_millis(step_in_millis),
+            max_steps,
+            accumulator: Duration::new(0, 0),
+            instant: clock.now(),
+            clock,
+        }
+    }
+
+    pub fn step(&self) -> Duration {
+        self.step
+    }
+
+    pub fn steps(&mut self) -> u32 {
+        let now = self.clock.now();
+        self.accumulator += now - self.instant;
+        self.instant = now;
+        let accumulated = self.accumulator.as_nanos();
+        let step = self.step.as_nanos();
+        self.accumulator = Duration::from_nanos((accumulated % step) as u64);
+        //Drops steps over max so slow game don't fall further behind on every iteration.
+        (accumulated / step).min(u128::from(self.max_steps)) as u32
+    }
+}
@@ -1,29 +1,32 @@
-use crate::entities::{CommandPacket, Exception, StatePacket};
+use crate::entities::{CommandPacket, DisconnectPacket, Envelope, Exception, StatePacket};
 
-const PROTOCOL_VERSION: u8 = 2;
+const PROTOCOL_VERSION: u8 = 12;
 
 pub trait IWithVersion {
     fn get(&self) -> u8;
-    fn set(&mut self, version: u8);
 }
 
-impl IWithVersion for StatePacket {
+impl<S> IWithVersion for StatePacket<S> {
     fn get(&self) -> u8 {
         self.protocol_version
     }
+}
 
-    fn set(&mut self, version: u8) {
-        self.protocol_version = version
+impl<C> IWithVersion for CommandPacket<C> {
+    fn get(&self) -> u8 {
+        self.protocol_version
     }
 }
 
-impl IWithVersion for CommandPacket {
+impl IWithVersion for DisconnectPacket {
     fn get(&self) -> u8 {
         self.protocol_version
     }
+}
 
-    fn set(&mut self, version: u8) {
-        self.protocol_version = version
+impl<M> IWithVersion for Envelope<M> {
+    fn get(&self) -> u8 {
+        self.protocol_version
     }
 }
 
@@ -34,6 +37,7 @@ impl VersionChecker {
         if data.get() == PROTOCOL_VERSION {
             Ok(())
         } else {
+            warn!(version = data.get(), "packet of other protocol version dropped");
             Err(Exception::BadProtocolVersion)
         }
     }
@@ -0,0 +1,95 @@
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+///Source of time for server and client.
+/// Replace it with [`ManualClock`] to test timeouts without real time passing.
+pub trait Clock: Send + Sync {
+    ///Time elapsed from UNIX epoch. Must never go back.
+    fn now(&self) -> Duration;
+    ///Blocks current thread.
+    fn sleep(&self, duration: Duration);
+    ///Blocks thread waiting for work of other threads.
+    /// Unlike sleep it must not move time of clock.
+    fn wait(&self, duration: Duration) {
+        self.sleep(duration)
+    }
+}
+
+///Real time clock. Used by default.
+#[derive(Debug, Clone)]
+pub struct SystemClock {
+    start: Duration,
+    instant: Instant,
+}
+
+impl SystemClock {
+    pub fn new() -> SystemClock {
+        let start = match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(d) => d,
+            Err(e) => e.duration(),
+        };
+        SystemClock {
+            start,
+            instant: Instant::now(),
+        }
+    }
+}
+
+impl Default for SystemClock {
+    fn default() -> SystemClock {
+        SystemClock::new()
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.start + self.instant.elapsed()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration)
+    }
+}
+
+///Clock moved only by hand.
+/// Clone it to share same time between server, clients and test.
+#[derive(Debug, Clone, Default)]
+pub struct ManualClock {
+    now: Arc<(Mutex<Duration>, Condvar)>,
+}
+
+impl ManualClock {
+    pub fn new(now: Duration) -> ManualClock {
+        ManualClock {
+            now: Arc::new((Mutex::new(now), Condvar::new())),
+        }
+    }
+
+    ///Moves time forward.
+    pub fn advance(&self, duration: Duration) {
+        let (now, moved) = &*self.now;
+        *now.lock().unwrap() += duration;
+        moved.notify_all();
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Duration {
+        *self.now.0.lock().unwrap()
+    }
+
+    ///Don't block and moves time forward instead.
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration)
+    }
+
+    ///Waits until time moved by hand.
+    /// Not longer than duration of real time so thread waiting for other work don't hang.
+    fn wait(&self, duration: Duration) {
+        let (now, moved) = &*self.now;
+        let start = now.lock().unwrap();
+        let time = *start;
+        let _ = moved.wait_timeout_while(start, duration, |now| *now == time);
+    }
+}
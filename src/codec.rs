@@ -0,0 +1,75 @@
+use crate::entities::Exception;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+///Serializes packets before send and deserializes them after recv.
+/// Client and server must use same codec.
+pub trait Codec {
+    fn serialize<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, Exception>;
+    fn deserialize<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, Exception>;
+}
+
+///Default codec. Fast and compact but only for Rust clients.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Bincode;
+
+impl Codec for Bincode {
+    fn serialize<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, Exception> {
+        Ok(bincode::serialize(value)?)
+    }
+
+    fn deserialize<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, Exception> {
+        Ok(bincode::deserialize(bytes)?)
+    }
+}
+
+///MessagePack codec for clients not written in Rust.
+#[cfg(feature = "msgpack")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MessagePack;
+
+#[cfg(feature = "msgpack")]
+impl Codec for MessagePack {
+    fn serialize<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, Exception> {
+        rmp_serde::to_vec_named(value).map_err(|e| Exception::CodecError(Box::new(e)))
+    }
+
+    fn deserialize<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, Exception> {
+        rmp_serde::from_slice(bytes).map_err(|e| Exception::CodecError(Box::new(e)))
+    }
+}
+
+///CBOR codec for clients not written in Rust.
+#[cfg(feature = "cbor")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Cbor;
+
+#[cfg(feature = "cbor")]
+impl Codec for Cbor {
+    fn serialize<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, Exception> {
+        let mut bytes = Vec::new();
+        ciborium::ser::into_writer(value, &mut bytes)
+            .map_err(|e| Exception::CodecError(Box::new(e)))?;
+        Ok(bytes)
+    }
+
+    fn deserialize<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, Exception> {
+        ciborium::de::from_reader(bytes).map_err(|e| Exception::CodecError(Box::new(e)))
+    }
+}
+
+///JSON codec. Slow and big but human readable so use it for debugging.
+#[cfg(feature = "json")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Json;
+
+#[cfg(feature = "json")]
+impl Codec for Json {
+    fn serialize<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, Exception> {
+        serde_json::to_vec(value).map_err(|e| Exception::CodecError(Box::new(e)))
+    }
+
+    fn deserialize<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, Exception> {
+        serde_json::from_slice(bytes).map_err(|e| Exception::CodecError(Box::new(e)))
+    }
+}
@@ -0,0 +1,113 @@
+use crate::clock::{Clock, ManualClock};
+use crate::data_access_layer::transport::Transport;
+use crate::entities::{CapturedDatagram, Direction, Exception};
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+pub struct Recorder {
+    writer: Box<dyn Write + Send>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Recorder {
+    pub fn new(writer: Box<dyn Write + Send>, clock: Arc<dyn Clock>) -> Recorder {
+        Recorder { writer, clock }
+    }
+
+    pub fn record(
+        &mut self,
+        direction: Direction,
+        peer: SocketAddr,
+        data: &[u8],
+    ) -> Result<(), Exception> {
+        let datagram = CapturedDatagram {
+            time: self.clock.now(),
+            direction,
+            peer,
+            data: data.to_vec(),
+        };
+        bincode::serialize_into(&mut self.writer, &datagram)?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+///Datagrams recorded by server or client.
+#[derive(Debug, Clone, Default)]
+pub struct Capture {
+    datagrams: Vec<CapturedDatagram>,
+}
+
+impl Capture {
+    ///Reads capture written with record method of [`crate::GameServerBuilder`] or [`crate::ClientSocketBuilder`].
+    pub fn read(mut reader: impl Read) -> Result<Capture, Exception> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        let mut datagrams = Vec::new();
+        let mut rest = &bytes[..];
+        while !rest.is_empty() {
+            datagrams.push(bincode::deserialize_from(&mut rest)?);
+        }
+        Ok(Capture { datagrams })
+    }
+
+    pub fn datagrams(&self) -> &[CapturedDatagram] {
+        &self.datagrams
+    }
+}
+
+///Transport giving received datagrams from capture on time of clock.
+/// If there is no datagram to give moves clock to time of next one.
+/// Sent datagrams are dropped.
+/// Clones share same datagrams.
+#[derive(Clone)]
+pub struct ReplayTransport {
+    address: SocketAddr,
+    datagrams: Arc<Mutex<VecDeque<CapturedDatagram>>>,
+    clock: ManualClock,
+}
+
+impl ReplayTransport {
+    pub fn new(address: SocketAddr, capture: Capture, clock: ManualClock) -> ReplayTransport {
+        ReplayTransport {
+            address,
+            datagrams: Arc::new(Mutex::new(capture.datagrams.into_iter().collect())),
+            clock,
+        }
+    }
+
+    ///True if all datagrams of capture were given.
+    pub fn is_finished(&self) -> bool {
+        self.datagrams.lock().unwrap().is_empty()
+    }
+}
+
+impl Transport for ReplayTransport {
+    fn send_to(&self, buf: &[u8], _addr: &SocketAddr) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        let mut datagrams = self.datagrams.lock().unwrap();
+        let now = self.clock.now();
+        while let Some(datagram) = datagrams.pop_front() {
+            if datagram.time > now {
+                self.clock.advance(datagram.time - now);
+                datagrams.push_front(datagram);
+                break;
+            }
+            if datagram.direction == Direction::Received {
+                let len = datagram.data.len().min(buf.len());
+                buf[..len].copy_from_slice(&datagram.data[..len]);
+                return Ok((len, datagram.peer));
+            }
+        }
+        Err(io::ErrorKind::WouldBlock.into())
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.address)
+    }
+}
@@ -0,0 +1,161 @@
+use crate::clock::{Clock, SystemClock};
+use crate::data_access_layer::transport::Transport;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+///Bad network to simulate.
+/// Percents are from 0 to 100.
+#[derive(Debug, Clone, Default)]
+pub struct LinkConditions {
+    ///Delay of every datagram.
+    pub latency: Duration,
+    ///Max random delay added to latency.
+    pub jitter: Duration,
+    ///Chance that datagram is lost.
+    pub loss_percent: f64,
+    ///Chance that datagram is sent twice.
+    pub duplicate_percent: f64,
+    ///Chance that datagram is swapped with next one.
+    pub reorder_percent: f64,
+}
+
+///Wraps [`Transport`] and makes its outgoing datagrams late, lost, duplicated or reordered.
+/// Same seed gives same decisions so bugs can be reproduced.
+/// Wrap both client and server to affect both directions.
+/// Delayed datagrams are sent on next call of send_to or recv_from.
+/// Datagram held to swap with next one is sent alone when its time passed and nothing came after it.
+pub struct LinkConditioner<N: Transport> {
+    transport: N,
+    conditions: LinkConditions,
+    state: Mutex<State>,
+    clock: Arc<dyn Clock>,
+}
+
+struct State {
+    random: Random,
+    delayed: Vec<(Duration, Vec<u8>, SocketAddr)>,
+    held: Option<(Duration, Vec<u8>, SocketAddr)>,
+}
+
+impl<N: Transport> LinkConditioner<N> {
+    pub fn new(transport: N, conditions: LinkConditions, seed: u64) -> LinkConditioner<N> {
+        LinkConditioner {
+            transport,
+            conditions,
+            state: Mutex::new(State {
+                random: Random::new(seed),
+                delayed: Vec::new(),
+                held: None,
+            }),
+            clock: Arc::new(SystemClock::new()),
+        }
+    }
+
+    ///Sets source of time for latency. By default it is [`SystemClock`].
+    pub fn clock(mut self, clock: impl Clock + 'static) -> LinkConditioner<N> {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    fn due(&self, state: &mut State) -> Duration {
+        let jitter = state.random.duration(self.conditions.jitter);
+        self.clock.now() + self.conditions.latency + jitter
+    }
+
+    fn delay(&self, state: &mut State, buf: Vec<u8>, addr: SocketAddr) {
+        let at = self.due(state);
+        state.delayed.push((at, buf, addr));
+    }
+
+    fn flush(&self, state: &mut State) -> io::Result<()> {
+        let now = self.clock.now();
+        if let Some((at, _, _)) = &state.held {
+            if *at <= now {
+                let (at, buf, addr) = state.held.take().unwrap();
+                state.delayed.push((at, buf, addr));
+            }
+        }
+        let (due, delayed) = state.delayed.drain(..).partition(|(at, _, _)| *at <= now);
+        state.delayed = delayed;
+        let mut due: Vec<(Duration, Vec<u8>, SocketAddr)> = due;
+        due.sort_by_key(|(at, _, _)| *at);
+        for (_, buf, addr) in due {
+            self.transport.send_to(&buf, &addr)?;
+        }
+        Ok(())
+    }
+}
+
+impl<N: Transport> Transport for LinkConditioner<N> {
+    fn send_to(&self, buf: &[u8], addr: &SocketAddr) -> io::Result<usize> {
+        let state = &mut *self.state.lock().unwrap();
+        if !state.random.chance(self.conditions.loss_percent) {
+            let copies = if state.random.chance(self.conditions.duplicate_percent) {
+                2
+            } else {
+                1
+            };
+            for _ in 0..copies {
+                if state.held.is_none() && state.random.chance(self.conditions.reorder_percent) {
+                    let at = self.due(state);
+                    state.held = Some((at, buf.to_vec(), *addr));
+                } else {
+                    self.delay(state, buf.to_vec(), *addr);
+                    if let Some((_, held, held_addr)) = state.held.take() {
+                        self.delay(state, held, held_addr);
+                    }
+                }
+            }
+        } else {
+            trace!(peer = %addr, "datagram lost by link conditioner");
+        }
+        self.flush(state)?;
+        Ok(buf.len())
+    }
+
+    fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        self.flush(&mut self.state.lock().unwrap())?;
+        self.transport.recv_from(buf)
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.transport.local_addr()
+    }
+
+    fn set_broadcast(&self, broadcast: bool) -> io::Result<()> {
+        self.transport.set_broadcast(broadcast)
+    }
+}
+
+///SplitMix64 generator. Small and same on every platform.
+struct Random(u64);
+
+impl Random {
+    fn new(seed: u64) -> Random {
+        Random(seed)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    fn chance(&mut self, percent: f64) -> bool {
+        let value = (self.next() >> 11) as f64 / (1u64 << 53) as f64;
+        value * 100.0 < percent
+    }
+
+    fn duration(&mut self, max: Duration) -> Duration {
+        let nanos = max.as_nanos() as u64;
+        if nanos == 0 {
+            Duration::new(0, 0)
+        } else {
+            Duration::from_nanos(self.next() % (nanos + 1))
+        }
+    }
+}
@@ -0,0 +1,54 @@
+use crate::entities::{DemoEvent, Exception};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::io::{Read, Write};
+
+pub struct DemoRecorder {
+    writer: Box<dyn Write + Send>,
+}
+
+impl DemoRecorder {
+    pub fn new(writer: Box<dyn Write + Send>) -> DemoRecorder {
+        DemoRecorder { writer }
+    }
+
+    pub fn record<C: Serialize, S: Serialize>(
+        &mut self,
+        event: &DemoEvent<C, S>,
+    ) -> Result<(), Exception> {
+        bincode::serialize_into(&mut self.writer, event)?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+///Match recorded by server.
+#[derive(Debug, Clone)]
+pub struct Demo<C, S> {
+    events: Vec<DemoEvent<C, S>>,
+}
+
+impl<C: DeserializeOwned, S: DeserializeOwned> Demo<C, S> {
+    ///Reads demo written with record_demo method of [`crate::GameServerBuilder`].
+    pub fn read(mut reader: impl Read) -> Result<Demo<C, S>, Exception> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        let mut events = Vec::new();
+        let mut rest = &bytes[..];
+        while !rest.is_empty() {
+            events.push(bincode::deserialize_from(&mut rest)?);
+        }
+        Ok(Demo { events })
+    }
+}
+
+impl<C, S> Demo<C, S> {
+    ///Events in order they happened.
+    pub fn events(&self) -> &[DemoEvent<C, S>] {
+        &self.events
+    }
+
+    pub fn into_events(self) -> Vec<DemoEvent<C, S>> {
+        self.events
+    }
+}
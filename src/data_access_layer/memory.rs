@@ -0,0 +1,107 @@
+use crate::data_access_layer::transport::Transport;
+use crate::entities::Exception;
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::sync::{Arc, Mutex};
+
+type Datagram = (Vec<u8>, SocketAddr);
+
+///In process network without OS sockets.
+/// Clone it to share same network between servers and clients.
+#[derive(Clone, Default)]
+pub struct MemoryNetwork {
+    endpoints: Arc<Mutex<HashMap<SocketAddr, Sender<Datagram>>>>,
+}
+
+impl MemoryNetwork {
+    pub fn new() -> MemoryNetwork {
+        MemoryNetwork::default()
+    }
+
+    ///Creates transport receiving datagrams sent to this address.
+    /// Returns [`std::io::ErrorKind::AddrInUse`] if address already bound.
+    pub fn bind(&self, address: impl ToSocketAddrs) -> Result<MemoryTransport, Exception> {
+        let address = address.to_socket_addrs()?.next().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "no address to bind")
+        })?;
+        let mut endpoints = self.endpoints.lock().unwrap();
+        if endpoints.contains_key(&address) {
+            return Err(io::Error::from(io::ErrorKind::AddrInUse).into());
+        }
+        let (sender, receiver) = channel();
+        endpoints.insert(address, sender);
+        Ok(MemoryTransport {
+            address,
+            receiver: Mutex::new(receiver),
+            network: self.clone(),
+        })
+    }
+
+    fn send(&self, datagram: Datagram, to: &SocketAddr) {
+        let endpoints = self.endpoints.lock().unwrap();
+        if is_broadcast(to) {
+            for (address, sender) in endpoints.iter() {
+                if address.port() == to.port() && *address != datagram.1 {
+                    let _ = sender.send(datagram.clone());
+                }
+            }
+            return;
+        }
+        //Like UDP datagram to nobody is silently lost.
+        if let Some(sender) = endpoints.get(to) {
+            let _ = sender.send(datagram);
+        }
+    }
+
+    fn unbind(&self, address: &SocketAddr) {
+        self.endpoints.lock().unwrap().remove(address);
+    }
+}
+
+//Datagram to 255.255.255.255 reaches every endpoint with its port like broadcast in LAN.
+fn is_broadcast(address: &SocketAddr) -> bool {
+    match address.ip() {
+        IpAddr::V4(ip) => ip.is_broadcast(),
+        IpAddr::V6(_) => false,
+    }
+}
+
+///Endpoint of [`MemoryNetwork`].
+pub struct MemoryTransport {
+    address: SocketAddr,
+    //Mutex makes transport Sync so it can be shared by worker threads.
+    receiver: Mutex<Receiver<Datagram>>,
+    network: MemoryNetwork,
+}
+
+impl Transport for MemoryTransport {
+    fn send_to(&self, buf: &[u8], addr: &SocketAddr) -> io::Result<usize> {
+        self.network.send((buf.to_vec(), self.address), addr);
+        Ok(buf.len())
+    }
+
+    fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        match self.receiver.lock().unwrap().try_recv() {
+            Ok((data, from)) => {
+                let len = data.len().min(buf.len());
+                buf[..len].copy_from_slice(&data[..len]);
+                Ok((len, from))
+            }
+            Err(TryRecvError::Empty) | Err(TryRecvError::Disconnected) => {
+                Err(io::ErrorKind::WouldBlock.into())
+            }
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.address)
+    }
+}
+
+impl Drop for MemoryTransport {
+    fn drop(&mut self) {
+        self.network.unbind(&self.address);
+    }
+}
@@ -0,0 +1,153 @@
+use crate::clock::Clock;
+use crate::entities::Exception;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::time::Duration;
+
+const MAX_REQUEST_SIZE: usize = 1024;
+const MAX_SCRAPES: usize = 8;
+const SCRAPE_TIMEOUT_IN_MILLIS: u64 = 1_000;
+
+///Request being read or answer being written.
+struct Scrape {
+    stream: TcpStream,
+    request: Vec<u8>,
+    //Rest of answer not yet written. None while request is read.
+    response: Option<Vec<u8>>,
+    started: Duration,
+}
+
+///Serves metrics over HTTP without blocking game loop.
+/// Streams are not blocking so slow scraper only waits for next poll.
+/// Scrape not done in time is dropped.
+pub struct MetricsEndpoint {
+    listener: TcpListener,
+    scrapes: Vec<Scrape>,
+    clock: Arc<dyn Clock>,
+}
+
+impl MetricsEndpoint {
+    pub fn bind(
+        address: impl ToSocketAddrs,
+        clock: Arc<dyn Clock>,
+    ) -> Result<MetricsEndpoint, Exception> {
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+        Ok(MetricsEndpoint {
+            listener,
+            scrapes: Vec::new(),
+            clock,
+        })
+    }
+
+    ///Accepts new requests and goes on with started ones as far as it can without waiting.
+    /// Body is made once for all requests read in this call.
+    /// Returns last error. Scrape with error is dropped.
+    pub fn poll(&mut self, body: impl FnOnce() -> String) -> Result<(), Exception> {
+        let mut result = self.accept();
+        let now = self.clock.now();
+        let timeout = Duration::from_millis(SCRAPE_TIMEOUT_IN_MILLIS);
+        let mut body = Some(body);
+        let mut response: Option<Vec<u8>> = None;
+        let mut scrapes = Vec::new();
+        for mut scrape in self.scrapes.drain(..) {
+            if now - scrape.started > timeout {
+                trace!("metrics scrape timed out");
+                continue;
+            }
+            if scrape.response.is_none() {
+                match read(&mut scrape) {
+                    Ok(false) => {
+                        scrapes.push(scrape);
+                        continue;
+                    }
+                    Ok(true) => {}
+                    Err(e) => {
+                        result = Err(e.into());
+                        continue;
+                    }
+                }
+                if let Some(body) = body.take() {
+                    response = Some(render(&body()));
+                }
+                scrape.response = response.clone();
+            }
+            match write(&mut scrape) {
+                Ok(false) => scrapes.push(scrape),
+                Ok(true) => {
+                    let _ = scrape.stream.shutdown(Shutdown::Both);
+                }
+                Err(e) => result = Err(e.into()),
+            }
+        }
+        self.scrapes = scrapes;
+        result
+    }
+
+    fn accept(&mut self) -> Result<(), Exception> {
+        loop {
+            let stream = match self.listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(e) => return Err(e.into()),
+            };
+            if self.scrapes.len() >= MAX_SCRAPES {
+                trace!("metrics scrape over limit dropped");
+                continue;
+            }
+            stream.set_nonblocking(true)?;
+            self.scrapes.push(Scrape {
+                stream,
+                request: Vec::new(),
+                response: None,
+                started: self.clock.now(),
+            });
+        }
+    }
+}
+
+//Request is same for any path so it is only read to its end.
+// Returns true if whole request is read.
+fn read(scrape: &mut Scrape) -> io::Result<bool> {
+    let mut buf = [0u8; MAX_REQUEST_SIZE];
+    loop {
+        match scrape.stream.read(&mut buf) {
+            Ok(0) => return Ok(true),
+            Ok(size) => {
+                scrape.request.extend_from_slice(&buf[..size]);
+                let is_end = scrape.request.windows(4).any(|w| w == b"\r\n\r\n");
+                if is_end || scrape.request.len() >= MAX_REQUEST_SIZE {
+                    return Ok(true);
+                }
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(false),
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+//Returns true if whole answer is written.
+fn write(scrape: &mut Scrape) -> io::Result<bool> {
+    let response = scrape.response.as_mut().unwrap();
+    while !response.is_empty() {
+        match scrape.stream.write(response) {
+            Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+            Ok(size) => {
+                response.drain(..size);
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(false),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(true)
+}
+
+fn render(body: &str) -> Vec<u8> {
+    format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        body.len(),
+        body
+    )
+    .into_bytes()
+}
@@ -0,0 +1,344 @@
+pub mod capture;
+
+pub mod conditioner;
+
+pub mod demo;
+
+pub mod memory;
+
+pub mod metrics;
+
+pub mod room;
+
+pub mod transport;
+
+use self::capture::Recorder;
+use self::transport::Transport;
+use crate::codec::Codec;
+use crate::entities::{CommandPacket, Direction, Exception, ServerPacket};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs};
+
+struct ClientSocket<N: Transport> {
+    socket: N,
+    server_address: SocketAddr,
+}
+
+struct ServerSocket<N: Transport> {
+    socket: N,
+}
+
+pub const MAX_DATAGRAM_SIZE: usize = 64_000;
+
+impl<N: Transport> ClientSocket<N> {
+    fn new(socket: N, server_address: impl ToSocketAddrs) -> Result<ClientSocket<N>, Exception> {
+        let server_address = server_address.to_socket_addrs()?.next().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "no server address")
+        })?;
+        Ok(ClientSocket {
+            socket,
+            server_address,
+        })
+    }
+
+    fn read(&self, buffer: &mut [u8]) -> Result<usize, Exception> {
+        let (c, a) = self.socket.recv_from(buffer)?;
+        if a == self.server_address {
+            Ok(c)
+        } else {
+            debug!(from = %a, "datagram not from server dropped");
+            Err(Exception::NotValidIdError)
+        }
+    }
+
+    fn write(&self, buf: &[u8]) -> Result<usize, Exception> {
+        let r = self.socket.send_to(buf, &self.server_address)?;
+        Ok(r)
+    }
+}
+
+impl<N: Transport> ServerSocket<N> {
+    fn new(socket: N) -> ServerSocket<N> {
+        ServerSocket { socket }
+    }
+
+    fn read(&self, buffer: &mut [u8]) -> Result<(usize, SocketAddr), Exception> {
+        let (c, a) = self.socket.recv_from(buffer)?;
+        Ok((c, a))
+    }
+
+    fn write(&self, buf: &[u8], addr: &SocketAddr) -> Result<usize, Exception> {
+        let r = self.socket.send_to(buf, addr)?;
+        Ok(r)
+    }
+}
+
+struct BufferedServerSocket<N: Transport> {
+    socket: ServerSocket<N>,
+    buffer: Vec<u8>,
+}
+
+impl<N: Transport> BufferedServerSocket<N> {
+    fn new(socket: N) -> BufferedServerSocket<N> {
+        let socket = ServerSocket::new(socket);
+        let buffer = vec![0u8; MAX_DATAGRAM_SIZE];
+        BufferedServerSocket { socket, buffer }
+    }
+
+    fn read(&mut self) -> Result<(Vec<u8>, SocketAddr), Exception> {
+        let (c, a) = self.socket.read(&mut self.buffer)?;
+        Ok((self.buffer[..c].into(), a))
+    }
+
+    fn write(&self, addr: &SocketAddr, buffer: &[u8]) -> Result<usize, Exception> {
+        self.socket.write(buffer, addr)
+    }
+}
+
+struct BufferedClientSocket<N: Transport> {
+    socket: ClientSocket<N>,
+    buffer: Vec<u8>,
+}
+
+impl<N: Transport> BufferedClientSocket<N> {
+    fn new(
+        socket: N,
+        server_address: impl ToSocketAddrs,
+    ) -> Result<BufferedClientSocket<N>, Exception> {
+        let socket = ClientSocket::new(socket, server_address)?;
+        let buffer = vec![0u8; MAX_DATAGRAM_SIZE];
+        Ok(BufferedClientSocket { socket, buffer })
+    }
+
+    fn read(&mut self) -> Result<Vec<u8>, Exception> {
+        let r = self.socket.read(&mut self.buffer)?;
+        Ok(self.buffer[..r].into())
+    }
+
+    fn write(&self, buffer: &[u8]) -> Result<usize, Exception> {
+        self.socket.write(buffer)
+    }
+
+    fn server_address(&self) -> SocketAddr {
+        self.socket.server_address
+    }
+
+    fn connect(&mut self, server_address: SocketAddr) {
+        self.socket.server_address = server_address;
+    }
+}
+
+///Datagrams and bytes passed through socket.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Traffic {
+    pub packets_in: u64,
+    pub bytes_in: u64,
+    pub packets_out: u64,
+    pub bytes_out: u64,
+}
+
+//Capture is only for debugging so its error stops capture and don't lose datagram.
+fn record(
+    recorder: &mut Option<Recorder>,
+    error: &mut Option<Exception>,
+    direction: Direction,
+    address: SocketAddr,
+    bytes: &[u8],
+) {
+    if let Some(Err(e)) = recorder
+        .as_mut()
+        .map(|r| r.record(direction, address, bytes))
+    {
+        warn!(error = %e, "capture stopped");
+        *recorder = None;
+        *error = Some(e);
+    }
+}
+
+pub struct TypedServerSocket<K: Codec, N: Transport> {
+    socket: BufferedServerSocket<N>,
+    codec: K,
+    recorder: Option<Recorder>,
+    capture_error: Option<Exception>,
+    traffic: Traffic,
+}
+
+impl<K: Codec, N: Transport> TypedServerSocket<K, N> {
+    pub fn new(socket: N, codec: K) -> TypedServerSocket<K, N> {
+        let socket = BufferedServerSocket::new(socket);
+        TypedServerSocket {
+            socket,
+            codec,
+            recorder: None,
+            capture_error: None,
+            traffic: Traffic::default(),
+        }
+    }
+
+    pub fn traffic(&self) -> Traffic {
+        self.traffic
+    }
+
+    pub fn record(&mut self, recorder: Recorder) {
+        self.recorder = Some(recorder);
+    }
+
+    ///Error that stopped capture. Returned once.
+    pub fn take_capture_error(&mut self) -> Option<Exception> {
+        self.capture_error.take()
+    }
+
+    ///Reads packet of client or rendezvous.
+    pub fn read<P: DeserializeOwned>(
+        &mut self,
+    ) -> Result<(Result<P, Exception>, SocketAddr), Exception> {
+        let (b, a) = self.socket.read()?;
+        trace!(peer = %a, bytes = b.len(), "datagram received");
+        self.traffic.packets_in += 1;
+        self.traffic.bytes_in += b.len() as u64;
+        record(
+            &mut self.recorder,
+            &mut self.capture_error,
+            Direction::Received,
+            a,
+            &b,
+        );
+        let commands = self.codec.deserialize(&b);
+        Ok((commands, a))
+    }
+
+    ///Writes packet of server or rendezvous.
+    pub fn write<P: Serialize>(
+        &mut self,
+        addr: &SocketAddr,
+        packet: &P,
+    ) -> Result<usize, Exception> {
+        let bytes = self.codec.serialize(packet)?;
+        let size = self.socket.write(addr, &bytes)?;
+        trace!(peer = %addr, bytes = size, "datagram sent");
+        self.traffic.packets_out += 1;
+        self.traffic.bytes_out += size as u64;
+        record(
+            &mut self.recorder,
+            &mut self.capture_error,
+            Direction::Sent,
+            *addr,
+            &bytes,
+        );
+        Ok(size)
+    }
+}
+
+pub struct TypedClientSocket<K: Codec, N: Transport> {
+    socket: BufferedClientSocket<N>,
+    codec: K,
+    recorder: Option<Recorder>,
+    capture_error: Option<Exception>,
+}
+
+impl<K: Codec, N: Transport> TypedClientSocket<K, N> {
+    pub fn new(
+        socket: N,
+        server_address: impl ToSocketAddrs,
+        codec: K,
+    ) -> Result<TypedClientSocket<K, N>, Exception> {
+        let socket = BufferedClientSocket::new(socket, server_address)?;
+        Ok(TypedClientSocket {
+            socket,
+            codec,
+            recorder: None,
+            capture_error: None,
+        })
+    }
+
+    pub fn record(&mut self, recorder: Recorder) {
+        self.recorder = Some(recorder);
+    }
+
+    pub fn capture_error(&self) -> Option<&Exception> {
+        self.capture_error.as_ref()
+    }
+
+    ///Sends and receives datagrams only to and from this address from now.
+    pub fn connect(&mut self, server_address: SocketAddr) {
+        self.socket.connect(server_address);
+    }
+
+    pub fn read<S: DeserializeOwned>(&mut self) -> Result<ServerPacket<S>, Exception> {
+        let r = self.socket.read()?;
+        trace!(bytes = r.len(), "datagram received");
+        let address = self.socket.server_address();
+        record(
+            &mut self.recorder,
+            &mut self.capture_error,
+            Direction::Received,
+            address,
+            &r,
+        );
+        self.codec.deserialize(&r)
+    }
+
+    ///Writes packet of client or rendezvous.
+    pub fn write<P: Serialize>(&mut self, packet: &P) -> Result<usize, Exception> {
+        let bytes = self.codec.serialize(packet)?;
+        let size = self.socket.write(&bytes)?;
+        trace!(bytes = size, "datagram sent");
+        let address = self.socket.server_address();
+        record(
+            &mut self.recorder,
+            &mut self.capture_error,
+            Direction::Sent,
+            address,
+            &bytes,
+        );
+        Ok(size)
+    }
+}
+
+pub struct Cache<C> {
+    data: Vec<CommandPacket<C>>,
+}
+
+impl<C: Clone> Cache<C> {
+    const MAX_SAVED: usize = 200;
+    pub fn new() -> Cache<C> {
+        Cache { data: Vec::new() }
+    }
+    pub fn add(&mut self, command: CommandPacket<C>) {
+        if self.data.len() > Self::MAX_SAVED {
+            self.data = self
+                .data
+                .clone()
+                .into_iter()
+                .skip(Self::MAX_SAVED / 2)
+                .collect();
+        }
+        self.data.push(command);
+    }
+
+    pub fn get_max_id(&self) -> u32 {
+        self.data.iter()
+            .map(|x| x.id)
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn get(&mut self, id: u32) -> Option<CommandPacket<C>> {
+        self.data
+            .iter()
+            .position(|c| c.id == id)
+            .map(|i| self.data[i].clone())
+    }
+
+    pub fn get_range(&mut self, ids: &[u32]) -> Vec<CommandPacket<C>> {
+        let mut vec = Vec::<CommandPacket<C>>::new();
+        for id in ids {
+            if let Some(p) = self.get(*id) {
+                vec.push(p);
+            }
+        }
+        vec
+    }
+}
@@ -0,0 +1,83 @@
+use crate::data_access_layer::transport::Transport;
+use crate::data_access_layer::MAX_DATAGRAM_SIZE;
+use std::collections::VecDeque;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+type Inbox = Arc<Mutex<VecDeque<(Vec<u8>, SocketAddr)>>>;
+
+///Reads datagrams from transport shared by rooms.
+pub struct Router<N: Transport> {
+    transport: Arc<N>,
+    buffer: Vec<u8>,
+}
+
+impl<N: Transport> Router<N> {
+    pub fn new(transport: N) -> Router<N> {
+        Router {
+            transport: Arc::new(transport),
+            buffer: vec![0u8; MAX_DATAGRAM_SIZE],
+        }
+    }
+
+    pub fn recv(&mut self) -> io::Result<(Vec<u8>, SocketAddr)> {
+        let (c, a) = self.transport.recv_from(&mut self.buffer)?;
+        Ok((self.buffer[..c].into(), a))
+    }
+
+    ///Creates transport of room sending with shared transport.
+    pub fn room(&self) -> RoomTransport<N> {
+        RoomTransport {
+            transport: self.transport.clone(),
+            inbox: Inbox::default(),
+        }
+    }
+}
+
+///Transport of room. Gets datagrams routed to it by [`Router`].
+pub struct RoomTransport<N: Transport> {
+    transport: Arc<N>,
+    inbox: Inbox,
+}
+
+impl<N: Transport> RoomTransport<N> {
+    ///Handle to put datagrams to this room.
+    pub fn inbox(&self) -> RoomInbox {
+        RoomInbox {
+            inbox: self.inbox.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RoomInbox {
+    inbox: Inbox,
+}
+
+impl RoomInbox {
+    pub fn push(&self, datagram: Vec<u8>, from: SocketAddr) {
+        self.inbox.lock().unwrap().push_back((datagram, from));
+    }
+}
+
+impl<N: Transport> Transport for RoomTransport<N> {
+    fn send_to(&self, buf: &[u8], addr: &SocketAddr) -> io::Result<usize> {
+        self.transport.send_to(buf, addr)
+    }
+
+    fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        match self.inbox.lock().unwrap().pop_front() {
+            Some((datagram, from)) => {
+                let len = datagram.len().min(buf.len());
+                buf[..len].copy_from_slice(&datagram[..len]);
+                Ok((len, from))
+            }
+            None => Err(io::ErrorKind::WouldBlock.into()),
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.transport.local_addr()
+    }
+}
@@ -0,0 +1,63 @@
+use crate::entities::Exception;
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+
+///Sends and receives datagrams. By default it is [`UdpSocket`].
+/// Implement it to run framework over other network.
+pub trait Transport {
+    ///Sends datagram to address. Must not block current thread.
+    fn send_to(&self, buf: &[u8], addr: &SocketAddr) -> io::Result<usize>;
+    ///Receives datagram and address of its sender.
+    /// Must not block current thread and return [`std::io::ErrorKind::WouldBlock`] if there is no data available.
+    fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)>;
+    ///Address this transport is bound to.
+    fn local_addr(&self) -> io::Result<SocketAddr>;
+    ///Allows sending to broadcast address. Used to discover servers in LAN.
+    fn set_broadcast(&self, _broadcast: bool) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for UdpSocket {
+    fn send_to(&self, buf: &[u8], addr: &SocketAddr) -> io::Result<usize> {
+        UdpSocket::send_to(self, buf, addr)
+    }
+
+    fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        UdpSocket::recv_from(self, buf)
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        UdpSocket::local_addr(self)
+    }
+
+    fn set_broadcast(&self, broadcast: bool) -> io::Result<()> {
+        UdpSocket::set_broadcast(self, broadcast)
+    }
+}
+
+///Converts value to [`Transport`].
+/// Port converts to not blocking [`UdpSocket`] listening it.
+pub trait IntoTransport {
+    type Transport: Transport;
+    fn into_transport(self) -> Result<Self::Transport, Exception>;
+}
+
+impl IntoTransport for u16 {
+    type Transport = UdpSocket;
+
+    fn into_transport(self) -> Result<UdpSocket, Exception> {
+        let local_address = format!("0.0.0.0:{}", self);
+        let socket = UdpSocket::bind(local_address)?;
+        socket.set_nonblocking(true)?;
+        Ok(socket)
+    }
+}
+
+impl<T: Transport> IntoTransport for T {
+    type Transport = T;
+
+    fn into_transport(self) -> Result<T, Exception> {
+        Ok(self)
+    }
+}
@@ -0,0 +1,63 @@
+use crate::business_logic_layer as bll;
+use crate::entities::{ClientPacket, Discovery, ServerInfo, ServerPacket};
+use crate::{Clock, Codec, Exception, Transport, MAX_DATAGRAM_SIZE};
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+const IDLE_SLEEP_IN_MILLIS: u64 = 1;
+
+///Server in LAN answered discovery.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiscoveredServer {
+    ///Address to connect client to.
+    pub address: SocketAddr,
+    pub info: ServerInfo,
+    ///Time from probe to answer.
+    pub ping: Duration,
+}
+
+///Sends probe to address and collects answers until wait is over.
+pub fn discover<K: Codec, N: Transport>(
+    transport: &N,
+    codec: &K,
+    clock: &dyn Clock,
+    address: SocketAddr,
+    wait: Duration,
+) -> Result<Vec<DiscoveredServer>, Exception> {
+    transport.set_broadcast(true)?;
+    let probe = bll::new_token();
+    let packet = ClientPacket::<()>::Discovery(bll::envelope::packet(Discovery::Probe(probe)));
+    transport.send_to(&codec.serialize(&packet)?, &address)?;
+    let sent = clock.now();
+    let mut servers: Vec<DiscoveredServer> = Vec::new();
+    let mut buffer = vec![0u8; MAX_DATAGRAM_SIZE];
+    while clock.now() - sent < wait {
+        let (size, from) = match transport.recv_from(&mut buffer) {
+            Ok(r) => r,
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                clock.sleep(Duration::from_millis(IDLE_SLEEP_IN_MILLIS));
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        };
+        //Answers to other probes and not discovery datagrams are ignored.
+        let info = match codec.deserialize::<ServerPacket<()>>(&buffer[..size]) {
+            Ok(ServerPacket::Discovery(packet)) => match bll::envelope::open(packet) {
+                Ok(Discovery::Info(p, info)) if p == probe => info,
+                _ => continue,
+            },
+            _ => continue,
+        };
+        if servers.iter().all(|s| s.address != from) {
+            trace!(peer = %from, name = %info.name, "server discovered");
+            servers.push(DiscoveredServer {
+                address: from,
+                info,
+                ping: clock.now() - sent,
+            });
+        }
+    }
+    servers.sort_by_key(|s| s.ping);
+    Ok(servers)
+}
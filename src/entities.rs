@@ -1,28 +1,200 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize as SerializeTrait;
 use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::error::Error;
 use std::fmt::Display;
 use std::fmt::Formatter;
 use std::io;
+use std::net::SocketAddr;
 use std::time::Duration;
 
+///Command or state sent with framework.
+/// Implemented for all types that can be serialized, deserialized and cloned.
+pub trait Message: SerializeTrait + DeserializeOwned + Clone {}
+
+impl<T: SerializeTrait + DeserializeOwned + Clone> Message for T {}
+
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
-pub struct CommandPacket {
+pub struct CommandPacket<C> {
     pub protocol_id: u8,
     pub protocol_version: u8,
     pub id: u32,
-    pub command: Vec<u8>,
+    pub command: C,
     pub session_key: Duration,
+    pub last_state: u32,
+    pub ack_delay: Duration,
+    pub resume_token: Option<u64>,
+    ///Credentials sent until first state.
+    pub auth: Option<Vec<u8>>,
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
-pub struct StatePacket {
+pub struct StatePacket<S> {
     pub protocol_id: u8,
     pub protocol_version: u8,
     pub id: u32,
-    pub state: Vec<u8>,
+    pub state: S,
     pub session_key: Duration,
     pub last_received: u32,
-    pub sequence: u32
+    pub sequence: u32,
+    pub tick: u32,
+    pub resume_token: u64,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct DisconnectPacket {
+    pub protocol_id: u8,
+    pub protocol_version: u8,
+    pub session_key: Duration,
+    pub reason: String,
+}
+
+///Message of NAT hole punching.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub enum Rendezvous {
+    ///Game server registers itself with name.
+    Register(String),
+    ///Client asks to introduce it to game server with name.
+    Connect(String),
+    ///Public address of peer.
+    Introduce(SocketAddr),
+    ///First datagram to peer to open NAT mapping.
+    Punch,
+}
+
+///Message without session checked only by protocol id and version.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct Envelope<M> {
+    pub protocol_id: u8,
+    pub protocol_version: u8,
+    pub message: M,
+}
+
+pub type RendezvousPacket = Envelope<Rendezvous>;
+
+///Info about server from [`crate::Game::server_info`] for clients looking for servers in LAN.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Default)]
+pub struct ServerInfo {
+    pub name: String,
+    pub players: u32,
+    pub map: String,
+}
+
+///Message of LAN discovery.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub enum Discovery {
+    ///Client looks for servers. Number is echoed in answer.
+    Probe(u64),
+    ///Server answers probe.
+    Info(u64, ServerInfo),
+}
+
+pub type DiscoveryPacket = Envelope<Discovery>;
+
+///Status of server for server browsers.
+/// Players is count of connected clients set by server.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Default)]
+pub struct ServerStatus {
+    pub players: u32,
+    ///Zero if there is no limit.
+    pub max_players: u32,
+    ///Version of game. Not of lib.
+    pub version: String,
+    ///Any other values of game like map or mode.
+    pub values: BTreeMap<String, String>,
+}
+
+///Message of server query.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub enum Query {
+    ///Tool asks status. Number is echoed in answer.
+    Request(u64),
+    ///Server answers request.
+    Status(u64, ServerStatus),
+}
+
+pub type QueryPacket = Envelope<Query>;
+
+///Filter of servers listed by master server.
+/// Default filter lists all servers.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Default)]
+pub struct ServerFilter {
+    ///Only servers with this version of game.
+    pub version: Option<String>,
+    pub not_full: bool,
+    pub not_empty: bool,
+    ///Only servers with all these values.
+    pub values: BTreeMap<String, String>,
+}
+
+///Game server registered on master server.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone)]
+pub struct ListedServer {
+    pub address: SocketAddr,
+    pub status: ServerStatus,
+}
+
+///Message of master server.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub enum Master {
+    ///Game server registers itself with its status again and again.
+    Heartbeat(ServerStatus),
+    ///Client asks servers. Number is echoed in answer.
+    List(u64, ServerFilter),
+    ///Master server answers list request.
+    Servers(u64, Vec<ListedServer>),
+}
+
+pub type MasterPacket = Envelope<Master>;
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub enum ClientPacket<C> {
+    Command(CommandPacket<C>),
+    Disconnect(DisconnectPacket),
+    Rendezvous(RendezvousPacket),
+    Discovery(DiscoveryPacket),
+    Query(QueryPacket),
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub enum ServerPacket<S> {
+    State(StatePacket<S>),
+    Disconnect(DisconnectPacket),
+    Rendezvous(RendezvousPacket),
+    Discovery(DiscoveryPacket),
+    Query(QueryPacket),
+}
+
+///Direction of captured datagram.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Direction {
+    Sent,
+    Received,
+}
+
+///Datagram sent or received by server or client.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub struct CapturedDatagram {
+    ///Time of [`crate::Clock`] when datagram was sent or received.
+    pub time: Duration,
+    pub direction: Direction,
+    ///Address datagram was sent to or received from.
+    pub peer: SocketAddr,
+    pub data: Vec<u8>,
+}
+
+///What happened in game on tick.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+pub enum DemoEvent<C, S> {
+    ///Commands given to [`crate::Game::handle_command`].
+    Commands {
+        tick: u32,
+        from: crate::ClientId,
+        commands: Vec<C>,
+    },
+    ///State returned by [`crate::Game::draw`] at time of [`crate::Clock`].
+    Draw { tick: u32, time: Duration, state: S },
 }
 
 #[derive(Debug)]
@@ -36,6 +208,8 @@ pub enum Exception {
     BadProtocolVersion,
     ///Error on serialize or deserialize
     BincodeError(bincode::Error),
+    ///Error on serialize or deserialize with not default [`crate::Codec`].
+    CodecError(Box<dyn Error + Send + Sync>),
     /// Not ordered command or state received by this reason it was skipped.
     /// Maybe it is duplicated.
     /// Retry again.
@@ -44,16 +218,34 @@ pub enum Exception {
     /// Lib ignoring it.
     /// Retry again.
     NotValidIdError,
+    ///Other side closed connection with this reason.
+    Disconnected(String),
 }
 
 impl Error for Exception {}
 
+impl Exception {
+    ///Name of variant of error.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Exception::IoError(_) => "IoError",
+            Exception::BadProtocolVersion => "BadProtocolVersion",
+            Exception::BincodeError(_) => "BincodeError",
+            Exception::CodecError(_) => "CodecError",
+            Exception::NotOrderedPacketError => "NotOrderedPacketError",
+            Exception::NotValidIdError => "NotValidIdError",
+            Exception::Disconnected(_) => "Disconnected",
+        }
+    }
+}
+
 impl Display for Exception {
     fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
         match self {
             Exception::BadProtocolVersion => write!(f, "Different lib version on client and server. You must update client and server."),
             Exception::NotOrderedPacketError => write!(f, "Not ordered command or state received by this reason it was skipped. Maybe it is duplicated. Retry again."),
             Exception::NotValidIdError => write!(f, "Packet not from this lib. Lib ignoring it. Retry again."),
+            Exception::Disconnected(reason) => write!(f, "Connection closed: {}", reason),
             _ => write!(f, "{:#?}", self),
         }
     }
@@ -70,43 +262,3 @@ impl std::convert::From<bincode::Error> for Exception {
         Exception::BincodeError(err)
     }
 }
-//#[derive(Debug)]
-//pub struct LoggerMonad<T>(Result<T, Exception>);
-//
-//impl<T> LoggerMonad<T> {
-//    pub fn new(value: Result<T, Exception>) -> LoggerMonad<T> {
-//        LoggerMonad(value)
-//    }
-//
-//    pub fn and_then<U, F: FnOnce(T) -> LoggerMonad<U>>(self, f: F) -> LoggerMonad<U> {
-//        match self.0 {
-//            Ok(x) => {
-//                let monad = f(x);
-//                match &monad.0 {
-//                    Ok(_) => monad,
-//                    Err(e) => {
-//                        eprintln!("{:#?}", e);
-//                        monad
-//                    }
-//                }
-//            }
-//            Err(e) => LoggerMonad::new(Err(e)),
-//        }
-//    }
-//
-//    pub fn map<U, F: FnOnce(T) -> U>(self, f: F) -> LoggerMonad<U> {
-//        self.and_then(|x| LoggerMonad::new(Ok(f(x))))
-//    }
-//
-//    pub fn and<U>(self, data: LoggerMonad<U>) -> LoggerMonad<U> {
-//        self.and_then(|_| data)
-//    }
-//
-//    pub fn unwrap(self) -> T {
-//        self.0.unwrap()
-//    }
-//
-//    pub fn unwrap_or(self, def: T) -> T {
-//        self.0.unwrap_or(def)
-//    }
-//}
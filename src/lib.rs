@@ -1,13 +1,66 @@
+#[macro_use]
+mod trace;
+
 mod business_logic_layer;
+mod clock;
+mod codec;
 mod data_access_layer;
+mod discovery;
 mod entities;
+mod local;
+mod master;
+mod metrics;
+mod peers;
+mod query;
+mod rendezvous;
+mod replay;
+mod rooms;
 
 use crate::business_logic_layer as bll;
+use crate::business_logic_layer::queue::Admission;
+pub use crate::business_logic_layer::history::{History, Snapshot};
+pub use crate::clock::{Clock, ManualClock, SystemClock};
+#[cfg(feature = "cbor")]
+pub use crate::codec::Cbor;
+#[cfg(feature = "json")]
+pub use crate::codec::Json;
+#[cfg(feature = "msgpack")]
+pub use crate::codec::MessagePack;
+pub use crate::codec::{Bincode, Codec};
 pub use crate::data_access_layer::MAX_DATAGRAM_SIZE;
+pub use crate::data_access_layer::conditioner::{LinkConditioner, LinkConditions};
+pub use crate::data_access_layer::memory::{MemoryNetwork, MemoryTransport};
+use crate::data_access_layer::metrics::MetricsEndpoint;
+pub use crate::data_access_layer::transport::{IntoTransport, Transport};
+use crate::data_access_layer::capture::Recorder;
+pub use crate::data_access_layer::capture::{Capture, ReplayTransport};
+use crate::data_access_layer::demo::DemoRecorder;
+pub use crate::data_access_layer::demo::Demo;
 use crate::data_access_layer::{TypedClientSocket, TypedServerSocket};
-pub use crate::entities::Exception;
-use std::collections::HashMap;
-use std::net::{SocketAddr, ToSocketAddrs};
+use crate::entities::{
+    ClientPacket, CommandPacket, DisconnectPacket, Discovery, DiscoveryPacket, Master, Query,
+    QueryPacket, Rendezvous, RendezvousPacket, ServerPacket,
+};
+use crate::local::LocalPeer;
+use crate::peers::{Peers, Shards};
+pub use crate::discovery::DiscoveredServer;
+pub use crate::entities::{
+    CapturedDatagram, DemoEvent, Direction, Exception, ListedServer, Message, ServerFilter,
+    ServerInfo, ServerStatus,
+};
+pub use crate::local::LocalClient;
+pub use crate::master::MasterServer;
+pub use crate::metrics::Metrics;
+pub use crate::query::query_server;
+pub use crate::rendezvous::RendezvousServer;
+pub use crate::replay::{replay_client, replay_server, ReplayClient};
+pub use crate::rooms::{RoomId, RoomServer, MOVED_TO_ROOM};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::io::{self, Write};
+use std::marker::PhantomData;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 #[derive(Debug)]
@@ -17,292 +70,1818 @@ pub enum ServerEvent {
     ExceptionOnRecv(Exception),
     ///Error on write data to socket.
     ExceptionOnSend((SocketAddr, Exception)),
+    ///Error on read data from this client.
+    /// For example client sent command that can't be deserialized.
+    ExceptionOnRecvFrom((SocketAddr, Exception)),
+    ///Error on write demo. Recording stops after it and server keeps running.
+    ExceptionOnRecord(Exception),
+    ///Error on write capture. Capture stops after it and server keeps running.
+    ExceptionOnCapture(Exception),
+    ///Error on serve metrics.
+    ExceptionOnMetrics(Exception),
+    ///Client disconnected with reason and server removed it.
+    /// Silent client is removed with reason "Timed out" after idle timeout and grace period.
+    ClientDisconnected((ClientId, String)),
+    ///Silent client came back in grace period and got its session back.
+    ClientReconnected(ClientId),
+    ///New client waits in queue for free place on server with [`GameServerBuilder::max_clients`].
+    /// It gets session when place is free and all clients before it got theirs.
+    ClientQueued(SocketAddr),
+    ///New client was refused with [`SERVER_FULL`] because server and its queue are full.
+    ServerFull(SocketAddr),
 }
 
 pub type ContinueRunning = bool;
 
+///Id of client given by server when client connects.
+/// Stays same when client comes from new address with same session.
+pub type ClientId = u32;
+
+///Secret given by server to client to resume its session from other [`ClientSocket`].
+pub type ResumeToken = u64;
+
+///Number of simulation step of the game.
+pub type Tick = u32;
+
+///User authenticated by [`Game::authenticate`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UserData {
+    pub name: String,
+    ///Any other data of user like rank or team.
+    pub values: BTreeMap<String, String>,
+}
+
+///What client saw on its screen when it sent commands.
+/// Use it to rewind world for lag compensation.
+pub struct ClientView<'a, S> {
+    rtt: Duration,
+    time: Duration,
+    history: &'a History<S>,
+    user: Option<&'a UserData>,
+}
+
+impl<'a, S> ClientView<'a, S> {
+    fn new(
+        rtt: Duration,
+        now: Duration,
+        history: &'a History<S>,
+        user: Option<&'a UserData>,
+    ) -> ClientView<'a, S> {
+        //Command was in flight half of rtt and state client reacted to was in flight other half.
+        let time = now
+            .checked_sub(rtt + Duration::from_millis(INTERPOLATION_DELAY_IN_MILLIS))
+            .unwrap_or_else(|| Duration::new(0, 0));
+        ClientView {
+            rtt,
+            time,
+            history,
+            user,
+        }
+    }
+
+    ///User returned by [`Game::authenticate`] for this client.
+    /// None for clients added by game and local players.
+    pub fn user(&self) -> Option<&'a UserData> {
+        self.user
+    }
+
+    ///Estimated round trip time to client.
+    pub fn rtt(&self) -> Duration {
+        self.rtt
+    }
+
+    ///Estimated server [`Clock`] time of state client saw when it sent commands.
+    pub fn time(&self) -> Duration {
+        self.time
+    }
+
+    ///State client saw when it sent commands.
+    /// None if it is older than whole history.
+    pub fn snapshot(&self) -> Option<&'a Snapshot<S>> {
+        self.history.at(self.time)
+    }
+
+    ///Recent states of the game.
+    pub fn history(&self) -> &'a History<S> {
+        self.history
+    }
+}
+
 ///Game to use with server must implement this trait.
+/// Every method gets tick: number of last simulation step made by [`Game::update`].
 pub trait Game {
+    ///Command client sends to server.
+    type Command: Message;
+    ///State server sends to client.
+    type State: Message;
+    /// tick: number of this simulation step. Starts from 1.
+    /// fixed_dt: fixed time of simulation step.
+    /// Returns bool value indicating
+    /// should server continue running if false stops server.
+    /// Called with fixed rate about once in 16 milliseconds independently from commands and draw.
+    /// If server is late then called several times in a row to catch up.
+    /// Usually move physics and game logic here.
+    fn update(&mut self, _tick: Tick, _fixed_dt: Duration) -> ContinueRunning {
+        true
+    }
     /// delta_time: time elapsed from last call.
     /// command: ordered commands commands from server.
-    /// from: Address of command sender.
+    /// from: Id of command sender.
+    /// view: What sender saw when it sent commands. Use it for lag compensation.
     /// Returns bool value indicating
     /// should server continue running if false stops server.
     /// Called only when new commands come to server.
     /// Commands ordered and with some guarantees.
     fn handle_command(
         &mut self,
+        tick: Tick,
         delta_time: Duration,
-        commands: Vec<Vec<u8>>,
-        from: SocketAddr,
+        commands: Vec<Self::Command>,
+        from: ClientId,
+        view: ClientView<Self::State>,
     ) -> ContinueRunning;
     ///Gets new state to send to client.
     /// delta_time: time elapsed throw last call.
-    /// Returns bytes with new game state for client.
+    /// Returns new game state for client.
     /// Called once in about 30 milliseconds.
     /// Sends state only to clients connected to server.
     ///Ordered and without some guarantees.
-    /// If returns empty Vec<u8> then server skips sending it and go to next iteration
-    fn draw(&mut self, delta_time: Duration) -> Vec<u8>;
-    ///Allow client with this IP Address work with server.
-    /// If false server don't send new state to this client.
+    /// If returns None then server skips sending it and go to next iteration
+    fn draw(&mut self, tick: Tick, delta_time: Duration) -> Option<Self::State>;
+    ///Gets state to save in lag compensation history.
+    /// Called after each draw that returned state.
+    /// If returns None then server saves state returned from draw.
+    /// Usually don't implement this method. Use default implementation.
+    fn snapshot(&mut self, _tick: Tick) -> Option<Self::State> {
+        None
+    }
+    ///Checks payload client sent with [`ClientSocketBuilder::auth`].
+    /// Called once for new client before its first commands. Client without payload sends empty one.
+    /// Clients added by [`Game::add_client`] are not authenticated.
+    /// Returns user given back in [`ClientView::user`]
+    /// or reason sent to client as [`Exception::Disconnected`] and server removes client.
+    /// Usually don't implement this method. Use default implementation.
+    fn authenticate(
+        &mut self,
+        _tick: Tick,
+        _client: ClientId,
+        _payload: &[u8],
+    ) -> Result<UserData, String> {
+        Ok(UserData::default())
+    }
+    ///Allow client with this id and current IP Address work with server.
+    /// If false server removes client and it gets [`NOT_ALLOWED`] in [`Exception::Disconnected`].
     /// Usually don't implement this method. Use default implementation.
-    fn allow_connect(&mut self, _from: &SocketAddr) -> bool {
+    fn allow_connect(&mut self, _tick: Tick, _client: ClientId, _from: &SocketAddr) -> bool {
         true
     }
     ///Handles events from server.
     /// Returns bool value.
     /// If returns false stops server.
     /// Usually don't implement this method. Use default implementation.
-    fn handle_server_event(&mut self, _event: ServerEvent) -> ContinueRunning {
+    fn handle_server_event(&mut self, _tick: Tick, _event: ServerEvent) -> ContinueRunning {
         true
     }
     ///Client to add to recv state from server.
     /// If returns not None then servers on draw sends new state to this client.
     /// If client with this IP Address already connected then nothing happens.
+    /// Otherwise client gets new id.
     /// Usually don't implement this method. Use default implementation.
-    fn add_client(&mut self) -> Option<SocketAddr> {
+    fn add_client(&mut self, _tick: Tick) -> Option<SocketAddr> {
         None
     }
     ///Disconnect this client from server and don't send new state to them.
+    /// Client gets [`KICKED`] in [`Exception::Disconnected`]. Use [`GameServer::kick`] for other reason.
     /// Usually don't implement this method. Use default implementation.
-    fn remove_client(&mut self) -> Option<SocketAddr> {
+    fn remove_client(&mut self, _tick: Tick) -> Option<ClientId> {
+        None
+    }
+    ///Info sent to clients discovering servers in LAN with [`ClientSocketBuilder::discover`].
+    /// Called on draw. If returns None then server keeps last info.
+    /// Server never returned info don't answer discovery.
+    fn server_info(&mut self, _tick: Tick) -> Option<ServerInfo> {
+        None
+    }
+    ///Status sent to server browsers asking it with [`query_server`].
+    /// Called on draw. Server sets players to count of connected clients.
+    /// If returns None then server keeps last status.
+    /// Server never returned status don't answer queries.
+    fn server_status(&mut self, _tick: Tick) -> Option<ServerStatus> {
         None
     }
 }
 
 /// Client used to communicate with [`GameServer`]. Must be singleton in your app.
-pub struct ClientSocket {
-    socket: TypedClientSocket,
-    client: bll::Client,
+/// C: [`Game::Command`] of server game.
+/// S: [`Game::State`] of server game.
+/// K: [`Codec`] used by server.
+/// N: [`Transport`] to communicate with server.
+pub struct ClientSocket<C: Message, S: Message, K: Codec = Bincode, N: Transport = UdpSocket> {
+    socket: TypedClientSocket<K, N>,
+    client: bll::Client<C>,
+    tick: Tick,
+    is_disconnected: bool,
+    //Name of server and timer of requests while rendezvous don't introduced it.
+    rendezvous: Option<(String, bll::timer::WaitTimer)>,
+    state: PhantomData<S>,
 }
 
-impl ClientSocket {
+impl<C: Message, S: Message> ClientSocket<C, S> {
     ///Create new client and listen on port to recv packets from server_address and send its to them.
-    pub fn new(port: u16, server_address: impl ToSocketAddrs) -> Result<ClientSocket, Exception> {
-        Ok(ClientSocket {
-            socket: TypedClientSocket::new(port, server_address)?,
-            client: bll::Client::new(),
-        })
+    pub fn new(
+        port: u16,
+        server_address: impl ToSocketAddrs,
+    ) -> Result<ClientSocket<C, S>, Exception> {
+        ClientSocketBuilder::new(port).build(server_address)
     }
+}
 
+impl<C: Message, S: Message, K: Codec, N: Transport> ClientSocket<C, S, K, N> {
     ///Send data to server
     /// Don't block current thread
     /// may wait up to 30 milliseconds if you send commands too often
     ///Commands ordered and with some guarantees.
-    pub fn send(&mut self, command: Vec<u8>) -> Result<usize, Exception> {
+    /// Returns [`Exception`] with [`std::io::ErrorKind::WouldBlock`] until rendezvous introduced server.
+    pub fn send(&mut self, command: C) -> Result<usize, Exception> {
+        if let Some((name, timer)) = &mut self.rendezvous {
+            if timer.continue_execution() {
+                let packet = bll::envelope::packet(Rendezvous::Connect(name.clone()));
+                self.socket.write(&packet)?;
+            }
+            return Err(io::Error::from(io::ErrorKind::WouldBlock).into());
+        }
         let command = self.client.send(command);
-        self.socket.write(&command)
+        self.socket.write(&ClientPacket::Command(command))
+    }
+
+    ///Tells server to remove this client with reason.
+    /// Called on drop if it was not called before.
+    pub fn disconnect(&mut self, reason: &str) -> Result<usize, Exception> {
+        self.is_disconnected = true;
+        let packet = ClientPacket::<C>::Disconnect(self.client.disconnect(reason));
+        self.socket.write(&packet)
+    }
+
+    ///Error that stopped capture set with [`ClientSocketBuilder::record`].
+    /// Client keeps working without capture after it.
+    pub fn capture_error(&self) -> Option<&Exception> {
+        self.socket.capture_error()
     }
 
     ///Reads data from server.
     /// Don't block current thread.
     /// Return [`Exception`] with [`std::io::ErrorKind::WouldBlock`] if there is no data available.
     ///Data ordered and without some guarantees.
-    pub fn recv(&mut self) -> Result<Vec<u8>, Exception> {
-        let state = self.socket.read()?;
+    /// Return [`Exception::Disconnected`] if server closed connection.
+    pub fn recv(&mut self) -> Result<S, Exception> {
+        let state = match self.socket.read::<S>()? {
+            ServerPacket::State(state) => state,
+            ServerPacket::Disconnect(packet) => {
+                let reason = self.client.recv_disconnect(packet)?;
+                //Server removed client so there is no one to send disconnect to.
+                self.is_disconnected = true;
+                return Err(Exception::Disconnected(reason));
+            }
+            ServerPacket::Rendezvous(packet) => return Err(self.recv_rendezvous(packet)),
+            //Answers to discovery and query come only to asking client.
+            ServerPacket::Discovery(_) | ServerPacket::Query(_) => {
+                return Err(io::Error::from(io::ErrorKind::WouldBlock).into())
+            }
+        };
+        let tick = state.tick;
         let (state, lost) = self.client.recv(state)?;
+        self.tick = tick;
         for command in lost {
-            self.socket.write(&command)?;
+            self.socket.write(&ClientPacket::Command(command))?;
         }
         Ok(state)
     }
+
+    fn recv_rendezvous(&mut self, packet: RendezvousPacket) -> Exception {
+        match bll::envelope::open(packet) {
+            Ok(Rendezvous::Introduce(server)) if self.rendezvous.is_some() => {
+                info!(server = %server, "server introduced by rendezvous");
+                self.rendezvous = None;
+                self.socket.connect(server);
+                let packet = ClientPacket::<C>::Rendezvous(bll::envelope::packet(Rendezvous::Punch));
+                if let Err(e) = self.socket.write(&packet) {
+                    return e;
+                }
+            }
+            Ok(_) => {}
+            Err(e) => return e,
+        }
+        io::Error::from(io::ErrorKind::WouldBlock).into()
+    }
+
+    ///Server tick on which last received state was drawn.
+    pub fn tick(&self) -> Tick {
+        self.tick
+    }
+
+    ///Token to resume session from other client with [`ClientSocketBuilder::resume`].
+    /// None until first state received.
+    pub fn resume_token(&self) -> Option<ResumeToken> {
+        self.client.resume_token()
+    }
+
+    ///Closes client without disconnect so server keeps its session for grace period.
+    /// Returns token to resume it.
+    pub fn into_resume_token(mut self) -> Option<ResumeToken> {
+        self.is_disconnected = true;
+        self.resume_token()
+    }
+}
+
+impl<C: Message, S: Message, K: Codec, N: Transport> Drop for ClientSocket<C, S, K, N> {
+    fn drop(&mut self) {
+        if !self.is_disconnected && self.rendezvous.is_none() {
+            if let Err(_e) = self.disconnect(CLIENT_DROPPED) {
+                debug!(error = %_e, "disconnect not sent on drop");
+            }
+        }
+    }
 }
 
-struct ServerSocket {
-    socket: TypedServerSocket,
-    servers: HashMap<SocketAddr, bll::Server>,
+///Builder for [`ClientSocket`].
+pub struct ClientSocketBuilder<K: Codec, E: IntoTransport = u16> {
+    transport: E,
+    codec: K,
+    clock: Arc<dyn Clock>,
+    recorder: Option<Box<dyn Write + Send>>,
+    resume: Option<ResumeToken>,
+    auth: Option<Vec<u8>>,
 }
 
-impl ServerSocket {
-    pub fn new(port: u16) -> Result<ServerSocket, Exception> {
-        Ok(ServerSocket {
-            socket: TypedServerSocket::new(port)?,
-            servers: HashMap::new(),
+impl ClientSocketBuilder<Bincode> {
+    ///Creates builder of client listening port.
+    pub fn new(port: u16) -> ClientSocketBuilder<Bincode> {
+        ClientSocketBuilder {
+            transport: port,
+            codec: Bincode,
+            clock: Arc::new(SystemClock::new()),
+            recorder: None,
+            resume: None,
+            auth: None,
+        }
+    }
+}
+
+impl<K: Codec, E: IntoTransport> ClientSocketBuilder<K, E> {
+    ///Sets codec of packets. Must be same as codec of server.
+    pub fn codec<D: Codec>(self, codec: D) -> ClientSocketBuilder<D, E> {
+        ClientSocketBuilder {
+            transport: self.transport,
+            codec,
+            clock: self.clock,
+            recorder: self.recorder,
+            resume: self.resume,
+            auth: self.auth,
+        }
+    }
+
+    ///Sets transport used instead of UDP socket listening port.
+    pub fn transport<N: Transport>(self, transport: N) -> ClientSocketBuilder<K, N> {
+        ClientSocketBuilder {
+            transport,
+            codec: self.codec,
+            clock: self.clock,
+            recorder: self.recorder,
+            resume: self.resume,
+            auth: self.auth,
+        }
+    }
+
+    ///Sets source of time. By default it is [`SystemClock`].
+    pub fn clock(mut self, clock: impl Clock + 'static) -> ClientSocketBuilder<K, E> {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    ///Records every sent and received datagram to writer.
+    /// Read it back with [`Capture::read`] to replay it.
+    /// Error of writer stops capture, see [`ClientSocket::capture_error`].
+    pub fn record(mut self, writer: impl Write + Send + 'static) -> ClientSocketBuilder<K, E> {
+        self.recorder = Some(Box::new(writer));
+        self
+    }
+
+    ///Resumes session of other client with token from [`ClientSocket::resume_token`].
+    /// Server gives same [`ClientId`] if session is still alive or in grace period.
+    pub fn resume(mut self, token: ResumeToken) -> ClientSocketBuilder<K, E> {
+        self.resume = Some(token);
+        self
+    }
+
+    ///Sends credentials like token, password or ticket to [`Game::authenticate`] of server.
+    /// If server rejects them [`ClientSocket::recv`] returns [`Exception::Disconnected`] with reason.
+    pub fn auth(mut self, payload: impl Into<Vec<u8>>) -> ClientSocketBuilder<K, E> {
+        self.auth = Some(payload.into());
+        self
+    }
+
+    ///Creates client sending commands to server_address.
+    pub fn build<C: Message, S: Message>(
+        self,
+        server_address: impl ToSocketAddrs,
+    ) -> Result<ClientSocket<C, S, K, E::Transport>, Exception> {
+        let mut socket = TypedClientSocket::new(
+            self.transport.into_transport()?,
+            server_address,
+            self.codec,
+        )?;
+        if let Some(writer) = self.recorder {
+            socket.record(Recorder::new(writer, self.clock.clone()));
+        }
+        let mut client = bll::Client::new(self.clock);
+        if let Some(token) = self.resume {
+            client.resume(token);
+        }
+        if let Some(payload) = self.auth {
+            client.authenticate(payload);
+        }
+        Ok(ClientSocket {
+            socket,
+            client,
+            tick: 0,
+            is_disconnected: false,
+            rendezvous: None,
+            state: PhantomData,
         })
     }
 
-    pub fn recv(&mut self) -> Result<(Vec<Vec<u8>>, SocketAddr), Exception> {
-        let (command, from) = self.socket.read()?;
-        self.add(&from);
-        let command = self.servers.get_mut(&from).unwrap().recv(command)?;
-        Ok((command, from))
+    ///Sends probe to address and waits for servers to answer it.
+    /// Address is usually broadcast address with port of servers like `255.255.255.255:22222`.
+    /// Blocks current thread for wait. Returns servers answered with [`Game::server_info`] sorted by ping.
+    pub fn discover(
+        self,
+        address: impl ToSocketAddrs,
+        wait: Duration,
+    ) -> Result<Vec<DiscoveredServer>, Exception> {
+        let address = address.to_socket_addrs()?.next().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "no address to discover")
+        })?;
+        let transport = self.transport.into_transport()?;
+        discovery::discover(&transport, &self.codec, self.clock.as_ref(), address, wait)
+    }
+
+    ///Asks server status without connecting to it.
+    /// Repeats request until server answers or timeout.
+    /// Returns [`std::io::ErrorKind::TimedOut`] if server don't answer queries.
+    pub fn query_server(
+        self,
+        address: impl ToSocketAddrs,
+        timeout: Duration,
+    ) -> Result<ServerStatus, Exception> {
+        let address = address.to_socket_addrs()?.next().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "no server address")
+        })?;
+        let transport = self.transport.into_transport()?;
+        query::query(&transport, &self.codec, self.clock, address, timeout)
+    }
+
+    ///Asks master server for game servers passing filter.
+    /// Repeats request until master server answers or timeout.
+    /// See [`GameServerBuilder::master`].
+    pub fn list_servers(
+        self,
+        master: impl ToSocketAddrs,
+        filter: ServerFilter,
+        timeout: Duration,
+    ) -> Result<Vec<ListedServer>, Exception> {
+        let master = master.to_socket_addrs()?.next().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "no master server address")
+        })?;
+        let transport = self.transport.into_transport()?;
+        master::list(&transport, &self.codec, self.clock, master, filter, timeout)
+    }
+
+    ///Creates client asking rendezvous to introduce it to server registered with name.
+    /// Then client and server punch holes in their NAT to each other.
+    /// See [`GameServerBuilder::rendezvous`].
+    pub fn build_rendezvous<C: Message, S: Message>(
+        self,
+        rendezvous: impl ToSocketAddrs,
+        name: &str,
+    ) -> Result<ClientSocket<C, S, K, E::Transport>, Exception> {
+        let timer = bll::timer::WaitTimer::new(CONNECT_PERIOD_IN_MILLIS, self.clock.clone());
+        let mut client = self.build(rendezvous)?;
+        let packet = bll::envelope::packet(Rendezvous::Connect(name.to_owned()));
+        client.socket.write(&packet)?;
+        client.rendezvous = Some((name.to_owned(), timer));
+        Ok(client)
+    }
+}
+
+///How long server keeps sessions of silent clients.
+/// Without idle timeout sessions are kept until client disconnects.
+#[derive(Clone, Copy)]
+struct Timeouts {
+    idle: Option<Duration>,
+    grace: Duration,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Timeouts {
+            idle: None,
+            grace: Duration::from_millis(GRACE_PERIOD_IN_MILLIS),
+        }
+    }
+}
+
+///Limit of clients and size of queue of clients waiting for free place.
+#[derive(Clone, Copy, Default)]
+struct Capacity {
+    max_clients: Option<usize>,
+    queue: usize,
+}
+
+//Payload to authenticate comes only with first commands of new client.
+type Received<C> = Result<(Vec<C>, ClientId, SocketAddr, Option<Vec<u8>>), ServerEvent>;
+
+///Name server registers with on rendezvous.
+struct Registration {
+    address: SocketAddr,
+    name: String,
+    timer: bll::timer::WaitTimer,
+    is_registered: bool,
+}
+
+///Master server server sends its status to.
+struct Heartbeat {
+    address: SocketAddr,
+    timer: bll::timer::WaitTimer,
+    is_sent: bool,
+}
+
+//Session of client moved to socket of other room with same id.
+enum Session<C> {
+    Active(SocketAddr, bll::Server<C>),
+    //Time suspended session expires.
+    Suspended(Duration, bll::Server<C>),
+}
+
+struct ServerSocket<C: Message, K: Codec, N: Transport> {
+    socket: TypedServerSocket<K, N>,
+    servers: HashMap<ClientId, (SocketAddr, bll::Server<C>)>,
+    clients: HashMap<SocketAddr, ClientId>,
+    //Clients connected by themselves and not yet given to game.
+    unauthenticated: HashSet<ClientId>,
+    //Sessions of silent clients with time they expire.
+    suspended: HashMap<ClientId, (Duration, bll::Server<C>)>,
+    pending: VecDeque<Received<C>>,
+    ids: Arc<AtomicU32>,
+    //Sessions of clients in all shards. Suspended ones keep their place.
+    connected: Arc<AtomicUsize>,
+    capacity: Capacity,
+    queue: bll::queue::WaitQueue<SocketAddr>,
+    //Index of worker and number of workers if socket is one of shards.
+    shard: Option<(usize, usize)>,
+    timeouts: Timeouts,
+    rendezvous: Option<Registration>,
+    master: Option<Heartbeat>,
+    //Answer to discovery. Server without it is hidden.
+    info: Option<ServerInfo>,
+    //Answer to query. Server without it don't answer.
+    status: Option<ServerStatus>,
+    limiter: bll::query::RateLimiter,
+    clock: Arc<dyn Clock>,
+    out_of_order: u64,
+}
+
+impl<C: Message, K: Codec, N: Transport> ServerSocket<C, K, N> {
+    pub fn new(socket: N, codec: K, clock: Arc<dyn Clock>) -> ServerSocket<C, K, N> {
+        ServerSocket {
+            socket: TypedServerSocket::new(socket, codec),
+            servers: HashMap::new(),
+            clients: HashMap::new(),
+            unauthenticated: HashSet::new(),
+            suspended: HashMap::new(),
+            pending: VecDeque::new(),
+            ids: Arc::new(AtomicU32::new(0)),
+            connected: Arc::new(AtomicUsize::new(0)),
+            capacity: Capacity::default(),
+            queue: bll::queue::WaitQueue::new(
+                0,
+                Duration::from_millis(QUEUE_TIMEOUT_IN_MILLIS),
+                clock.clone(),
+            ),
+            shard: None,
+            timeouts: Timeouts::default(),
+            rendezvous: None,
+            master: None,
+            info: None,
+            status: None,
+            limiter: bll::query::RateLimiter::new(
+                MAX_QUERIES_PER_PERIOD,
+                Duration::from_millis(QUERY_PERIOD_IN_MILLIS),
+                clock.clone(),
+            ),
+            clock,
+            out_of_order: 0,
+        }
+    }
+
+    pub fn recv(&mut self) -> Received<C> {
+        if let Some(received) = self.pending.pop_front() {
+            return received;
+        }
+        let (command, from) = loop {
+            let (command, from) = self.socket.read().map_err(ServerEvent::ExceptionOnRecv)?;
+            let _peer = peer_span!(from);
+            match command {
+                Ok(ClientPacket::Command(command)) => {
+                    if self.admit(from, &command)? {
+                        break (command, from);
+                    }
+                }
+                Ok(ClientPacket::Disconnect(packet)) => {
+                    return Err(self.recv_disconnect(from, packet))
+                }
+                Ok(ClientPacket::Rendezvous(packet)) => {
+                    if let Err(e) = self.recv_rendezvous(from, packet) {
+                        return Err(ServerEvent::ExceptionOnRecvFrom((from, e)));
+                    }
+                }
+                Ok(ClientPacket::Discovery(packet)) => {
+                    if let Err(e) = self.recv_discovery(from, packet) {
+                        return Err(ServerEvent::ExceptionOnRecvFrom((from, e)));
+                    }
+                }
+                Ok(ClientPacket::Query(packet)) => {
+                    if let Err(e) = self.recv_query(from, packet) {
+                        return Err(ServerEvent::ExceptionOnRecvFrom((from, e)));
+                    }
+                }
+                Err(e) => return Err(ServerEvent::ExceptionOnRecvFrom((from, e))),
+            }
+        };
+        let _peer = peer_span!(from);
+        let mut is_resumed = false;
+        let client = match self.client(&from) {
+            Some(client) => client,
+            None => match self.session(&command) {
+                Some(client) if self.suspended.contains_key(&client) => {
+                    is_resumed = true;
+                    self.resume(client, from)
+                }
+                Some(client) => self.migrate(client, from),
+                None => {
+                    let client = self.add(&from);
+                    self.unauthenticated.insert(client);
+                    client
+                }
+            },
+        };
+        let auth = if self.unauthenticated.contains(&client) {
+            Some(command.auth.clone().unwrap_or_default())
+        } else {
+            None
+        };
+        let (_, server) = self.servers.get_mut(&client).unwrap();
+        let out_of_order = server.out_of_order();
+        let received = server
+            .recv(command)
+            .map(|commands| (commands, client, from, auth))
+            .map_err(|e| ServerEvent::ExceptionOnRecvFrom((from, e)));
+        if received.is_ok() {
+            self.unauthenticated.remove(&client);
+        }
+        self.out_of_order += server.out_of_order() - out_of_order;
+        if is_resumed {
+            //Game learns about client before its commands.
+            self.pending.push_back(received);
+            return Err(ServerEvent::ClientReconnected(client));
+        }
+        received
+    }
+
+    //New client gets session only if it has same version and there is free place for it.
+    // Returns false if client waits in queue.
+    fn admit(&mut self, from: SocketAddr, command: &CommandPacket<C>) -> Result<bool, ServerEvent> {
+        if self.client(&from).is_some() || self.session(command).is_some() {
+            return Ok(true);
+        }
+        if let Err(e) = bll::check_version(command) {
+            if let Err(e) = self.refuse(from, BAD_VERSION) {
+                return Err(ServerEvent::ExceptionOnSend((from, e)));
+            }
+            return Err(ServerEvent::ExceptionOnRecvFrom((from, e)));
+        }
+        let max = match self.capacity.max_clients {
+            Some(max) => max,
+            None => return Ok(true),
+        };
+        //Shards may admit few clients over limit if they come at same moment.
+        let is_free = self.connected.load(Ordering::Relaxed) < max;
+        match self.queue.admit(from, is_free) {
+            Admission::Admitted => Ok(true),
+            Admission::Waiting(_place) => {
+                trace!(place = _place, "command of waiting peer dropped");
+                Ok(false)
+            }
+            Admission::Queued => {
+                info!(peer = %from, "peer queued");
+                Err(ServerEvent::ClientQueued(from))
+            }
+            Admission::Full => {
+                if let Err(e) = self.refuse(from, SERVER_FULL) {
+                    self.pending
+                        .push_back(Err(ServerEvent::ExceptionOnSend((from, e))));
+                }
+                Err(ServerEvent::ServerFull(from))
+            }
+        }
+    }
+
+    pub fn set_capacity(&mut self, capacity: Capacity) {
+        self.capacity = capacity;
+        self.queue = bll::queue::WaitQueue::new(
+            capacity.queue,
+            Duration::from_millis(QUEUE_TIMEOUT_IN_MILLIS),
+            self.clock.clone(),
+        );
+    }
+
+    //Client from new address with key or token of existing session is same client
+    // behind new NAT mapping or on other network.
+    fn session(&self, command: &CommandPacket<C>) -> Option<ClientId> {
+        self.servers
+            .iter()
+            .map(|(id, (_, s))| (id, s))
+            .chain(self.suspended.iter().map(|(id, (_, s))| (id, s)))
+            .find(|(_, s)| s.is_session(command))
+            .map(|(id, _)| *id)
     }
 
-    pub fn remove(&mut self, client: &SocketAddr) {
-        self.servers.remove(&client);
+    fn resume(&mut self, client: ClientId, address: SocketAddr) -> ClientId {
+        let (_, server) = self.suspended.remove(&client).unwrap();
+        info!(client, peer = %address, "peer resumed");
+        self.clients.insert(address, client);
+        self.servers.insert(client, (address, server));
+        client
     }
 
-    pub fn add(&mut self, client: &SocketAddr) {
-        if !self.servers.contains_key(client) {
-            self.servers.insert(client.clone(), bll::Server::new());
+    //Client introduced by rendezvous is punched so its commands pass NAT of server.
+    // Punches of clients are only to open NAT of client.
+    fn recv_rendezvous(&mut self, from: SocketAddr, packet: RendezvousPacket) -> Result<(), Exception> {
+        let rendezvous = self.rendezvous.as_ref().map(|r| r.address);
+        match bll::envelope::open(packet)? {
+            Rendezvous::Introduce(client) if rendezvous == Some(from) => {
+                info!(peer = %client, "client introduced by rendezvous");
+                let punch = bll::envelope::packet(Rendezvous::Punch);
+                self.socket
+                    .write(&client, &ServerPacket::<()>::Rendezvous(punch))?;
+            }
+            _ => trace!("rendezvous packet ignored"),
+        }
+        Ok(())
+    }
+
+    //Client without session gets reason too. Limiter keeps server from answering flood of them.
+    fn refuse(&mut self, to: SocketAddr, reason: &str) -> Result<(), Exception> {
+        if !self.limiter.allow(to.ip()) {
+            trace!("refusal over limit not sent");
+            return Ok(());
+        }
+        info!(peer = %to, reason = %reason, "peer refused");
+        let packet = ServerPacket::<()>::Disconnect(bll::reject(reason));
+        self.socket.write(&to, &packet)?;
+        Ok(())
+    }
+
+    //Probe don't create session so anyone in LAN can look for servers.
+    fn recv_discovery(&mut self, from: SocketAddr, packet: DiscoveryPacket) -> Result<(), Exception> {
+        let message = bll::envelope::open(packet)?;
+        if !self.limiter.allow(from.ip()) {
+            trace!("discovery over limit ignored");
+            return Ok(());
+        }
+        if let (Discovery::Probe(probe), Some(info)) = (message, &self.info) {
+            trace!("discovery answered");
+            let answer = bll::envelope::packet(Discovery::Info(probe, info.clone()));
+            self.socket
+                .write(&from, &ServerPacket::<()>::Discovery(answer))?;
+        } else {
+            trace!("discovery packet ignored");
+        }
+        Ok(())
+    }
+
+    //Query don't create session too. Limiter shares requests with discovery.
+    fn recv_query(&mut self, from: SocketAddr, packet: QueryPacket) -> Result<(), Exception> {
+        let message = bll::envelope::open(packet)?;
+        if !self.limiter.allow(from.ip()) {
+            trace!("query over limit ignored");
+            return Ok(());
+        }
+        if let (Query::Request(request), Some(status)) = (message, &self.status) {
+            trace!("query answered");
+            let answer = bll::envelope::packet(Query::Status(request, status.clone()));
+            self.socket
+                .write(&from, &ServerPacket::<()>::Query(answer))?;
+        } else {
+            trace!("query packet ignored");
+        }
+        Ok(())
+    }
+
+    ///Registers on rendezvous again so it don't forget server and NAT keeps mapping to it.
+    fn register(&mut self) -> Result<(), Exception> {
+        if let Some(r) = &mut self.rendezvous {
+            if !r.is_registered || r.timer.continue_execution() {
+                r.is_registered = true;
+                let packet = bll::envelope::packet(Rendezvous::Register(r.name.clone()));
+                self.socket.write(&r.address, &packet)?;
+            }
+        }
+        Ok(())
+    }
+
+    ///Sends status to master server so it keeps server in list.
+    /// Server without status is not listed.
+    fn heartbeat(&mut self) -> Result<(), Exception> {
+        if let (Some(m), Some(status)) = (&mut self.master, &self.status) {
+            if !m.is_sent || m.timer.continue_execution() {
+                m.is_sent = true;
+                let packet = bll::envelope::packet(Master::Heartbeat(status.clone()));
+                self.socket.write(&m.address, &packet)?;
+            }
+        }
+        Ok(())
+    }
+
+    ///Expires sessions, queue and limits of queries, reports error of capture, registers on rendezvous and sends heartbeat to master server.
+    pub fn maintain(&mut self) -> Vec<ServerEvent> {
+        self.limiter.expire();
+        self.queue.expire();
+        let mut events = self.expire();
+        if let Some(e) = self.socket.take_capture_error() {
+            events.push(ServerEvent::ExceptionOnCapture(e));
+        }
+        if let Err(e) = self.register() {
+            let address = self.rendezvous.as_ref().map(|r| r.address).unwrap();
+            events.push(ServerEvent::ExceptionOnSend((address, e)));
+        }
+        if let Err(e) = self.heartbeat() {
+            let address = self.master.as_ref().map(|m| m.address).unwrap();
+            events.push(ServerEvent::ExceptionOnSend((address, e)));
+        }
+        events
+    }
+
+    ///Suspends sessions of silent clients and removes suspended ones after grace period.
+    fn expire(&mut self) -> Vec<ServerEvent> {
+        let now = self.clock.now();
+        let timeout = match self.timeouts.idle {
+            Some(timeout) => timeout,
+            None => return Vec::new(),
+        };
+        let idle: Vec<ClientId> = self
+            .servers
+            .iter()
+            .filter(|(_, (_, s))| s.idle() > timeout)
+            .map(|(id, _)| *id)
+            .collect();
+        for client in idle {
+            let (address, server) = self.servers.remove(&client).unwrap();
+            info!(client, peer = %address, "peer suspended");
+            self.clients.remove(&address);
+            self.suspended
+                .insert(client, (now + self.timeouts.grace, server));
+        }
+        let expired: Vec<ClientId> = self
+            .suspended
+            .iter()
+            .filter(|(_, (until, _))| *until <= now)
+            .map(|(id, _)| *id)
+            .collect();
+        expired
+            .into_iter()
+            .map(|client| {
+                self.suspended.remove(&client);
+                self.connected.fetch_sub(1, Ordering::Relaxed);
+                info!(client, "suspended peer removed");
+                ServerEvent::ClientDisconnected((client, TIMED_OUT.to_owned()))
+            })
+            .collect()
+    }
+
+    fn migrate(&mut self, client: ClientId, to: SocketAddr) -> ClientId {
+        let (address, _) = self.servers.get_mut(&client).unwrap();
+        info!(client, from = %address, to = %to, "peer migrated");
+        self.clients.remove(address);
+        *address = to;
+        self.clients.insert(to, client);
+        client
+    }
+
+    fn recv_disconnect(&mut self, from: SocketAddr, packet: DisconnectPacket) -> ServerEvent {
+        let client = match self.client(&from) {
+            Some(client) => client,
+            None => return ServerEvent::ExceptionOnRecvFrom((from, Exception::NotValidIdError)),
+        };
+        let (_, server) = self.servers.get_mut(&client).unwrap();
+        match server.recv_disconnect(packet) {
+            Ok(reason) => {
+                self.remove(client);
+                ServerEvent::ClientDisconnected((client, reason))
+            }
+            Err(e) => ServerEvent::ExceptionOnRecvFrom((from, e)),
+        }
+    }
+
+    pub fn client(&self, address: &SocketAddr) -> Option<ClientId> {
+        self.clients.get(address).copied()
+    }
+
+    ///True if client has session. Suspended one too.
+    pub fn contains(&self, client: ClientId) -> bool {
+        self.servers.contains_key(&client) || self.suspended.contains_key(&client)
+    }
+
+    pub fn heartbeat_to(&mut self, address: SocketAddr) {
+        self.master = Some(Heartbeat {
+            address,
+            timer: bll::timer::WaitTimer::new(REGISTER_PERIOD_IN_MILLIS, self.clock.clone()),
+            is_sent: false,
+        });
+    }
+
+    pub fn register_on(&mut self, address: SocketAddr, name: String) {
+        self.rendezvous = Some(Registration {
+            address,
+            name,
+            timer: bll::timer::WaitTimer::new(REGISTER_PERIOD_IN_MILLIS, self.clock.clone()),
+            is_registered: false,
+        });
+    }
+
+    pub fn remove(&mut self, client: ClientId) {
+        let removed = match self.servers.remove(&client) {
+            Some((address, _)) => {
+                info!(client, peer = %address, "peer removed");
+                self.clients.remove(&address);
+                true
+            }
+            None => self.suspended.remove(&client).is_some(),
+        };
+        if removed {
+            self.connected.fetch_sub(1, Ordering::Relaxed);
         }
+        self.unauthenticated.remove(&client);
     }
 
-    pub fn send_to_all(&mut self, state: Vec<u8>) -> Vec<(SocketAddr, Exception)> {
+    ///Removes session of client without disconnect so socket sharing ids can continue it.
+    fn take(&mut self, client: ClientId) -> Option<Session<C>> {
+        let session = match self.servers.remove(&client) {
+            Some((address, server)) => {
+                self.clients.remove(&address);
+                Session::Active(address, server)
+            }
+            None => {
+                let (until, server) = self.suspended.remove(&client)?;
+                Session::Suspended(until, server)
+            }
+        };
+        self.unauthenticated.remove(&client);
+        self.connected.fetch_sub(1, Ordering::Relaxed);
+        Some(session)
+    }
+
+    fn put(&mut self, client: ClientId, session: Session<C>) {
+        match session {
+            Session::Active(address, server) => {
+                self.clients.insert(address, client);
+                self.servers.insert(client, (address, server));
+            }
+            Session::Suspended(until, server) => {
+                self.suspended.insert(client, (until, server));
+            }
+        }
+        self.connected.fetch_add(1, Ordering::Relaxed);
+    }
+
+    ///Sends reason to client and removes it.
+    pub fn reject(
+        &mut self,
+        client: ClientId,
+        reason: &str,
+    ) -> Result<(), (SocketAddr, Exception)> {
+        if let Some((address, server)) = self.servers.get(&client) {
+            let address = *address;
+            let packet = ServerPacket::<()>::Disconnect(server.disconnect(reason));
+            let result = self.socket.write(&address, &packet);
+            self.remove(client);
+            result.map_err(|e| (address, e))?;
+        } else {
+            //Suspended client has no address to send reason to.
+            // Its session is removed so it can't resume it.
+            self.remove(client);
+        }
+        Ok(())
+    }
+
+    ///Returns id of client with this address. New client gets new id.
+    pub fn add(&mut self, address: &SocketAddr) -> ClientId {
+        if let Some(client) = self.client(address) {
+            return client;
+        }
+        let client = self.new_id();
+        info!(client, peer = %address, "peer added");
+        self.clients.insert(*address, client);
+        let server = bll::Server::new(self.clock.clone(), self.new_token());
+        self.servers.insert(client, (*address, server));
+        self.connected.fetch_add(1, Ordering::Relaxed);
+        client
+    }
+
+    pub fn new_id(&self) -> ClientId {
+        self.ids.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    //Worker of shards gives tokens routed to itself so client resumes on same worker.
+    fn new_token(&self) -> ResumeToken {
+        loop {
+            let token = bll::new_token();
+            match self.shard {
+                Some((index, workers)) if peers::shard(&token, workers) != index => continue,
+                _ => return token,
+            }
+        }
+    }
+
+    ///True if some client must resend lost commands.
+    pub fn is_waiting(&self) -> bool {
+        self.servers.values().any(|(_, s)| s.is_waiting())
+    }
+
+    pub fn rtt(&self, client: ClientId) -> Duration {
+        self.servers
+            .get(&client)
+            .map_or_else(|| Duration::new(0, 0), |(_, s)| s.rtt())
+    }
+
+    pub fn send_to_all<S: Message>(
+        &mut self,
+        state: S,
+        tick: Tick,
+    ) -> Vec<(SocketAddr, Exception)> {
+        let mut exceptions = Vec::new();
+        for (a, s) in self.servers.values_mut() {
+            let packet = ServerPacket::State(s.send(state.clone(), tick));
+            if let Err(e) = self.socket.write(a, &packet) {
+                exceptions.push((*a, e));
+            }
+        }
+        exceptions
+    }
+
+    pub fn disconnect_all(&mut self, reason: &str) -> Vec<(SocketAddr, Exception)> {
         let mut exceptions = Vec::new();
-        for (a, s) in &mut self.servers {
-            let _ = self
-                .socket
-                .write(a, &s.send(state.clone()))
-                .map_err(|e| exceptions.push((*a, e)));
+        for (a, s) in self.servers.values() {
+            let packet = ServerPacket::<()>::Disconnect(s.disconnect(reason));
+            if let Err(e) = self.socket.write(a, &packet) {
+                exceptions.push((*a, e));
+            }
         }
         exceptions
     }
 }
 
 const DRAW_PERIOD_IN_MILLIS: u64 = 30;
+const TICK_PERIOD_IN_MILLIS: u64 = 16;
+const MAX_TICKS_PER_ITERATION: u32 = 5;
+const INTERPOLATION_DELAY_IN_MILLIS: u64 = DRAW_PERIOD_IN_MILLIS * 2;
+const HISTORY_SIZE: usize = 64;
+const MAX_FLUSHED_PACKETS: usize = 1024;
+const FLUSH_TIMEOUT_IN_MILLIS: u64 = 250;
+const FLUSH_PERIOD_IN_MILLIS: u64 = 10;
+///Reason of client not allowed by [`Game::allow_connect`].
+pub const NOT_ALLOWED: &str = "Not allowed to connect";
+///Reason of client removed by [`Game::remove_client`].
+pub const KICKED: &str = "Kicked by server";
+///Reason of new client with other protocol version.
+pub const BAD_VERSION: &str = "Bad protocol version";
+///Reason of new client refused by server with [`GameServerBuilder::max_clients`] and full queue.
+pub const SERVER_FULL: &str = "Server full";
+const STOPPED_BY_GAME: &str = "Server stopped";
+const CLIENT_DROPPED: &str = "Client dropped";
+const MAX_QUERIES_PER_PERIOD: u32 = 10;
+const QUERY_PERIOD_IN_MILLIS: u64 = 1_000;
+const QUERY_TIMEOUT_IN_MILLIS: u64 = 1_000;
+const TIMED_OUT: &str = "Timed out";
+const GRACE_PERIOD_IN_MILLIS: u64 = 0;
+const CONNECT_PERIOD_IN_MILLIS: u64 = 250;
+const REGISTER_PERIOD_IN_MILLIS: u64 = 1_000;
+const QUEUE_TIMEOUT_IN_MILLIS: u64 = 3_000;
+const REGISTRATION_TIMEOUT_IN_MILLIS: u64 = 5_000;
+
+fn is_would_block(event: &ServerEvent) -> bool {
+    match event {
+        ServerEvent::ExceptionOnRecv(Exception::IoError(e)) => {
+            e.kind() == std::io::ErrorKind::WouldBlock
+        }
+        _ => false,
+    }
+}
+
+///Stops [`GameServer::run`] from any thread.
+#[derive(Clone, Default)]
+pub struct ShutdownHandle {
+    reason: Arc<Mutex<Option<String>>>,
+}
+
+impl ShutdownHandle {
+    ///Asks server to stop and send reason to its clients.
+    pub fn shutdown(&self, reason: impl Into<String>) {
+        *self.reason.lock().unwrap() = Some(reason.into());
+    }
+
+    ///True if server was asked to stop.
+    pub fn is_shutdown(&self) -> bool {
+        self.reason.lock().unwrap().is_some()
+    }
+
+    fn reason(&self) -> Option<String> {
+        self.reason.lock().unwrap().clone()
+    }
+}
+
+///What server did before [`GameServer::run`] returned.
+#[derive(Debug, Clone)]
+pub struct ShutdownSummary {
+    ///Reason sent to clients.
+    pub reason: String,
+    ///Last simulated tick.
+    pub tick: Tick,
+    ///Clients notified about shutdown.
+    pub peers: usize,
+    pub metrics: Metrics,
+}
 
 ///Game server to run [`Game`]
-pub struct GameServer<T: Game> {
+pub struct GameServer<T: Game, K: Codec = Bincode, N: Transport = UdpSocket> {
     game: T,
-    socket: ServerSocket,
+    socket: Peers<T::Command, T::State, K, N>,
     is_running: bool,
     draw_timer: bll::timer::WaitTimer,
+    tick_timer: bll::timer::StepTimer,
     update_timer: bll::timer::ElapsedTimer,
     after_draw_elapsed_timer: bll::timer::ElapsedTimer,
+    history: History<T::State>,
+    tick: Tick,
+    clock: Arc<dyn Clock>,
+    demo: Option<DemoRecorder>,
+    metrics: Metrics,
+    metrics_endpoint: Option<MetricsEndpoint>,
+    shutdown: ShutdownHandle,
+    locals: Vec<LocalPeer<T::Command, T::State>>,
+    //Last status of game. Players are counted on each draw.
+    status: Option<ServerStatus>,
+    users: HashMap<ClientId, UserData>,
+    //Sent again on close so clients resend lost commands.
+    last_state: Option<(T::State, Tick)>,
 }
 
 impl<T: Game> GameServer<T> {
     ///Crates new server listening port
     pub fn new(game: T, port: u16) -> Result<GameServer<T>, Exception> {
-        Ok(GameServer {
+        GameServerBuilder::new(port).build(game)
+    }
+}
+
+///Builder for [`GameServer`].
+pub struct GameServerBuilder<K: Codec, E: IntoTransport = u16> {
+    transport: E,
+    codec: K,
+    clock: Arc<dyn Clock>,
+    recorder: Option<Box<dyn Write + Send>>,
+    demo: Option<Box<dyn Write + Send>>,
+    metrics: Option<SocketAddr>,
+    timeouts: Timeouts,
+    capacity: Capacity,
+    rendezvous: Option<(SocketAddr, String)>,
+    master: Option<SocketAddr>,
+}
+
+impl GameServerBuilder<Bincode> {
+    ///Creates builder of server listening port.
+    pub fn new(port: u16) -> GameServerBuilder<Bincode> {
+        GameServerBuilder {
+            transport: port,
+            codec: Bincode,
+            clock: Arc::new(SystemClock::new()),
+            recorder: None,
+            demo: None,
+            metrics: None,
+            timeouts: Timeouts::default(),
+            capacity: Capacity::default(),
+            rendezvous: None,
+            master: None,
+        }
+    }
+}
+
+impl<K: Codec, E: IntoTransport> GameServerBuilder<K, E> {
+    ///Sets codec of packets. Clients must use same codec.
+    pub fn codec<D: Codec>(self, codec: D) -> GameServerBuilder<D, E> {
+        GameServerBuilder {
+            transport: self.transport,
+            codec,
+            clock: self.clock,
+            recorder: self.recorder,
+            demo: self.demo,
+            metrics: self.metrics,
+            timeouts: self.timeouts,
+            capacity: self.capacity,
+            rendezvous: self.rendezvous,
+            master: self.master,
+        }
+    }
+
+    ///Sets transport used instead of UDP socket listening port.
+    pub fn transport<N: Transport>(self, transport: N) -> GameServerBuilder<K, N> {
+        GameServerBuilder {
+            transport,
+            codec: self.codec,
+            clock: self.clock,
+            recorder: self.recorder,
+            demo: self.demo,
+            metrics: self.metrics,
+            timeouts: self.timeouts,
+            capacity: self.capacity,
+            rendezvous: self.rendezvous,
+            master: self.master,
+        }
+    }
+
+    ///Sets source of time. By default it is [`SystemClock`].
+    pub fn clock(mut self, clock: impl Clock + 'static) -> GameServerBuilder<K, E> {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    ///Records every sent and received datagram to writer.
+    /// Read it back with [`Capture::read`] to replay it.
+    /// Error of writer stops capture and is reported as [`ServerEvent::ExceptionOnCapture`].
+    pub fn record(mut self, writer: impl Write + Send + 'static) -> GameServerBuilder<K, E> {
+        self.recorder = Some(Box::new(writer));
+        self
+    }
+
+    ///Records commands given to game and states drawn by game to writer.
+    /// Read it back with [`Demo::read`] to play it with [`ReplayClient`].
+    /// Error of writer stops recording and is reported as [`ServerEvent::ExceptionOnRecord`].
+    pub fn record_demo(mut self, writer: impl Write + Send + 'static) -> GameServerBuilder<K, E> {
+        self.demo = Some(Box::new(writer));
+        self
+    }
+
+    ///Serves [`Metrics`] in Prometheus text format over HTTP on address.
+    pub fn serve_metrics(mut self, address: SocketAddr) -> GameServerBuilder<K, E> {
+        self.metrics = Some(address);
+        self
+    }
+
+    ///Client not sending commands for this time is suspended and don't get new states.
+    /// Clients added by [`Game::add_client`] are suspended too if they don't send commands.
+    /// By default there is no timeout and silent clients keep their sessions.
+    pub fn idle_timeout(mut self, timeout: Option<Duration>) -> GameServerBuilder<K, E> {
+        self.timeouts.idle = timeout;
+        self
+    }
+
+    ///Time server keeps session of suspended client so it can resume it.
+    /// After it client is removed with [`ServerEvent::ClientDisconnected`].
+    /// By default it is zero and client is removed right after idle timeout.
+    pub fn grace_period(mut self, period: Duration) -> GameServerBuilder<K, E> {
+        self.timeouts.grace = period;
+        self
+    }
+
+    ///Limits number of clients with session. Suspended clients keep their place.
+    /// Server refuses new clients over it with [`ServerEvent::ServerFull`].
+    /// Clients added by [`Game::add_client`] and local clients are not limited.
+    /// Rooms of [`RoomServer`] share it.
+    /// By default there is no limit.
+    pub fn max_clients(mut self, max: usize) -> GameServerBuilder<K, E> {
+        self.capacity.max_clients = Some(max);
+        self
+    }
+
+    ///Puts new clients over [`GameServerBuilder::max_clients`] to queue of this size instead of refusing them.
+    /// Waiting client don't get states and loses its place if it don't send commands for 3 seconds.
+    /// Sharded server has queue of this size on every worker and [`RoomServer`] on every room.
+    pub fn wait_queue(mut self, size: usize) -> GameServerBuilder<K, E> {
+        self.capacity.queue = size;
+        self
+    }
+
+    ///Registers server with name on rendezvous so clients behind NAT can reach it.
+    /// See [`ClientSocketBuilder::build_rendezvous`].
+    pub fn rendezvous(mut self, address: SocketAddr, name: &str) -> GameServerBuilder<K, E> {
+        self.rendezvous = Some((address, name.to_owned()));
+        self
+    }
+
+    ///Sends status from [`Game::server_status`] to master server about once in second.
+    /// Master server lists server to clients while it gets status.
+    /// See [`ClientSocketBuilder::list_servers`].
+    /// Not supported by [`RoomServer`].
+    pub fn master(mut self, address: SocketAddr) -> GameServerBuilder<K, E> {
+        self.master = Some(address);
+        self
+    }
+
+    ///Creates server running game.
+    pub fn build<T: Game>(self, game: T) -> Result<GameServer<T, K, E::Transport>, Exception> {
+        let clock = self.clock;
+        let mut socket = ServerSocket::new(
+            self.transport.into_transport()?,
+            self.codec,
+            clock.clone(),
+        );
+        if let Some(writer) = self.recorder {
+            socket.socket.record(Recorder::new(writer, clock.clone()));
+        }
+        socket.timeouts = self.timeouts;
+        socket.set_capacity(self.capacity);
+        if let Some((address, name)) = self.rendezvous {
+            socket.register_on(address, name);
+        }
+        if let Some(address) = self.master {
+            socket.heartbeat_to(address);
+        }
+        let mut server = GameServer::with_peers(game, Peers::Local(Box::new(socket)), clock);
+        server.demo = self.demo.map(DemoRecorder::new);
+        if let Some(address) = self.metrics {
+            server.metrics_endpoint = Some(MetricsEndpoint::bind(address, server.clock.clone())?);
+        }
+        Ok(server)
+    }
+
+    ///Creates server running many games in rooms on same transport.
+    /// Rooms share ids of clients and [`GameServerBuilder::max_clients`].
+    /// Demo, capture, metrics endpoint and rendezvous are not used by rooms.
+    /// Returns [`std::io::ErrorKind::InvalidInput`] with [`GameServerBuilder::master`]
+    /// because rooms have no status of whole server.
+    pub fn build_rooms<T: Game>(self) -> Result<RoomServer<T, K, E::Transport>, Exception>
+    where
+        K: Clone,
+    {
+        if self.master.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "master server is not supported by rooms",
+            )
+            .into());
+        }
+        Ok(RoomServer::new(
+            self.transport.into_transport()?,
+            self.codec,
+            self.clock,
+            self.timeouts,
+            self.capacity,
+        ))
+    }
+
+    ///Creates rendezvous introducing clients to game servers behind NAT.
+    /// Game servers register on it with [`GameServerBuilder::rendezvous`].
+    /// Demo, capture and metrics endpoint are not used by rendezvous.
+    pub fn build_rendezvous(self) -> Result<RendezvousServer<K, E::Transport>, Exception> {
+        Ok(RendezvousServer::new(
+            self.transport.into_transport()?,
+            self.codec,
+            self.clock,
+        ))
+    }
+
+    ///Creates master server listing game servers to clients.
+    /// Game servers send heartbeat to it with [`GameServerBuilder::master`].
+    /// Demo, capture and metrics endpoint are not used by master server.
+    pub fn build_master(self) -> Result<MasterServer<K, E::Transport>, Exception> {
+        Ok(MasterServer::new(
+            self.transport.into_transport()?,
+            self.codec,
+            self.clock,
+        ))
+    }
+
+    ///Creates server sharding clients across worker threads.
+    /// Game runs on thread calling [`GameServer::run`] so it don't need to be [`Send`].
+    /// Workers own sessions of clients: decode, order and send packets.
+    /// Demo and metrics endpoint are used, capture is not.
+    pub fn build_sharded<T: Game>(
+        self,
+        game: T,
+        workers: usize,
+    ) -> Result<GameServer<T, K, E::Transport>, Exception>
+    where
+        K: Clone + Send + 'static,
+        E::Transport: Send + Sync + 'static,
+        T::Command: Send + 'static,
+        T::State: Send + 'static,
+    {
+        let clock = self.clock;
+        let mut shards = Shards::new(
+            self.transport.into_transport()?,
+            self.codec,
+            workers,
+            clock.clone(),
+            self.timeouts,
+            self.rendezvous,
+            self.master,
+        );
+        shards.set_capacity(self.capacity);
+        let mut server = GameServer::with_peers(game, Peers::Sharded(Box::new(shards)), clock);
+        server.demo = self.demo.map(DemoRecorder::new);
+        if let Some(address) = self.metrics {
+            server.metrics_endpoint = Some(MetricsEndpoint::bind(address, server.clock.clone())?);
+        }
+        Ok(server)
+    }
+}
+
+impl<T: Game, K: Codec, N: Transport> GameServer<T, K, N> {
+    fn with_peers(
+        game: T,
+        socket: Peers<T::Command, T::State, K, N>,
+        clock: Arc<dyn Clock>,
+    ) -> GameServer<T, K, N> {
+        GameServer {
             game,
-            socket: ServerSocket::new(port)?,
+            socket,
             is_running: true,
-            draw_timer: bll::timer::WaitTimer::new(DRAW_PERIOD_IN_MILLIS),
-            update_timer: bll::timer::ElapsedTimer::new(),
-            after_draw_elapsed_timer: bll::timer::ElapsedTimer::new(),
-        })
+            draw_timer: bll::timer::WaitTimer::new(DRAW_PERIOD_IN_MILLIS, clock.clone()),
+            tick_timer: bll::timer::StepTimer::new(
+                TICK_PERIOD_IN_MILLIS,
+                MAX_TICKS_PER_ITERATION,
+                clock.clone(),
+            ),
+            update_timer: bll::timer::ElapsedTimer::new(clock.clone()),
+            after_draw_elapsed_timer: bll::timer::ElapsedTimer::new(clock.clone()),
+            history: History::new(HISTORY_SIZE),
+            tick: 0,
+            clock,
+            demo: None,
+            metrics: Metrics::default(),
+            metrics_endpoint: None,
+            shutdown: ShutdownHandle::default(),
+            locals: Vec::new(),
+            status: None,
+            users: HashMap::new(),
+            last_state: None,
+        }
+    }
+}
+
+impl<T: Game, K: Codec, N: Transport> GameServer<T, K, N> {
+    ///Runs game update - simulate - draw circle.
+    /// Blocks current thread until game or [`ShutdownHandle`] stops it.
+    /// Then handles commands already received and sends disconnect to every client.
+    pub fn run(&mut self) -> ShutdownSummary {
+        while self.is_running && !self.shutdown.is_shutdown() {
+            self.iterate();
+        }
+        self.close()
+    }
+
+    ///Runs one update - simulate - draw circle.
+    /// Use it instead of [`GameServer::run`] to play with [`LocalClient`] on same thread.
+    /// Returns false if game or [`ShutdownHandle`] stopped server. Then call [`GameServer::close`].
+    pub fn run_once(&mut self) -> ContinueRunning {
+        if self.is_running && !self.shutdown.is_shutdown() {
+            self.iterate();
+        }
+        self.is_running && !self.shutdown.is_shutdown()
+    }
+
+    ///Handle to stop server from other thread.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    ///Connects player hosting game in this process.
+    /// Game gets its commands with new [`ClientId`] like commands of remote clients.
+    pub fn connect_local(&mut self) -> LocalClient<T::Command, T::State> {
+        let client = self.socket.new_id();
+        info!(client, "local peer added");
+        let (peer, local) = LocalPeer::new(client);
+        self.locals.push(peer);
+        local
+    }
+
+    ///Removes client and sends reason to it as [`Exception::Disconnected`].
+    /// Local client gets reason too. Suspended client can't resume its session after it.
+    /// Game gets [`ServerEvent::ClientDisconnected`] with reason like on timeout.
+    /// Unknown or already removed client is ignored.
+    pub fn kick(&mut self, client: ClientId, reason: &str) {
+        if let Some(local) = self.locals.iter().find(|l| l.client() == client) {
+            local.close(reason);
+            self.locals.retain(|l| l.client() != client);
+        } else if self.socket.contains(client) {
+            if let Err(ex) = self.socket.reject(client, reason) {
+                self.handle_server_event(ServerEvent::ExceptionOnSend(ex));
+            }
+        } else {
+            debug!(client, "unknown peer not kicked");
+            return;
+        }
+        info!(client, reason = %reason, "peer kicked");
+        self.handle_server_event(ServerEvent::ClientDisconnected((client, reason.to_owned())));
     }
-    ///Runs game update - draw circle.
-    /// Blocks current thread.
-    pub fn run(&mut self) {
-        while self.is_running {
-            self.update();
-            self.draw()
+
+    ///Handles commands already received and sends disconnect to every client.
+    /// Before it waits for commands lost on the way, see [`GameServer::flush`].
+    /// Called by [`GameServer::run`].
+    pub fn close(&mut self) -> ShutdownSummary {
+        let reason = self
+            .shutdown
+            .reason()
+            .unwrap_or_else(|| STOPPED_BY_GAME.to_owned());
+        info!(reason = %reason, "server shutdown");
+        //Stopped game must not get more commands.
+        if self.is_running {
+            for _ in 0..MAX_FLUSHED_PACKETS {
+                if !self.update() {
+                    break;
+                }
+            }
+            self.flush();
+            self.update_locals();
+        }
+        let peers = self.peers();
+        for local in self.locals.drain(..) {
+            local.close(&reason);
+        }
+        for ex in self.socket.disconnect_all(&reason) {
+            self.handle_server_event(ServerEvent::ExceptionOnSend(ex));
+        }
+        self.is_running = false;
+        ShutdownSummary {
+            reason,
+            tick: self.tick,
+            peers,
+            metrics: self.metrics(),
+        }
+    }
+
+    ///Sends last state again while some client must resend commands lost on the way.
+    /// Client resends them when it sees in state what server got.
+    /// Waits for them not longer than 250 milliseconds.
+    /// Commands lost after last received one are not known to server so they are not waited.
+    pub fn flush(&mut self) {
+        let (state, tick) = match &self.last_state {
+            Some(last) => last.clone(),
+            None => return,
+        };
+        let deadline = self.clock.now() + Duration::from_millis(FLUSH_TIMEOUT_IN_MILLIS);
+        while self.is_running && self.socket.is_waiting() && self.clock.now() < deadline {
+            debug!("waiting for lost commands");
+            for ex in self.socket.send_to_all(state.clone(), tick) {
+                self.handle_server_event(ServerEvent::ExceptionOnSend(ex));
+            }
+            self.clock.sleep(Duration::from_millis(FLUSH_PERIOD_IN_MILLIS));
+            for _ in 0..MAX_FLUSHED_PACKETS {
+                if !self.update() {
+                    break;
+                }
+            }
+        }
+    }
+
+    ///Snapshot of counters and gauges of server.
+    pub fn metrics(&self) -> Metrics {
+        let traffic = self.socket.traffic();
+        Metrics {
+            peers: self.peers(),
+            packets_in: traffic.packets_in,
+            bytes_in: traffic.bytes_in,
+            packets_out: traffic.packets_out,
+            bytes_out: traffic.bytes_out,
+            out_of_order: self.socket.out_of_order(),
+            ..self.metrics.clone()
+        }
+    }
+
+    fn peers(&self) -> usize {
+        self.socket.len() + self.locals.len()
+    }
+
+    fn iterate(&mut self) {
+        self.update();
+        self.update_locals();
+        self.simulate();
+        self.draw();
+        self.serve_metrics()
+    }
+
+    fn serve_metrics(&mut self) {
+        if let Some(mut endpoint) = self.metrics_endpoint.take() {
+            let result = endpoint.poll(|| self.metrics().to_prometheus());
+            self.metrics_endpoint = Some(endpoint);
+            if let Err(e) = result {
+                self.handle_server_event(ServerEvent::ExceptionOnMetrics(e));
+            }
+        }
+    }
+
+    fn simulate(&mut self) {
+        let fixed_dt = self.tick_timer.step();
+        for _ in 0..self.tick_timer.steps() {
+            if !self.is_running {
+                return;
+            }
+            self.tick += 1;
+            let start = self.clock.now();
+            self.is_running &= self.game.update(self.tick, fixed_dt);
+            self.metrics.tick_duration = self.clock.now() - start;
         }
     }
 
     fn draw(&mut self) {
         if self.draw_timer.continue_execution() {
-            let state = self.game.draw(self.after_draw_elapsed_timer.elapsed());
-            if state.is_empty() {
-                return;
+            for event in self.socket.maintain() {
+                self.handle_server_event(event);
+            }
+            let tick = self.tick;
+            if let Some(info) = self.game.server_info(tick) {
+                self.socket.set_info(info);
+            }
+            if let Some(status) = self.game.server_status(tick) {
+                self.status = Some(status);
+            }
+            if let Some(mut status) = self.status.clone() {
+                status.players = self.peers() as u32;
+                self.socket.set_status(status);
+            }
+            let state = match self
+                .game
+                .draw(tick, self.after_draw_elapsed_timer.elapsed())
+            {
+                Some(state) => state,
+                None => return,
+            };
+            self.record_demo(DemoEvent::Draw {
+                tick,
+                time: self.clock.now(),
+                state: state.clone(),
+            });
+            let snapshot = self
+                .game
+                .snapshot(tick)
+                .unwrap_or_else(|| state.clone());
+            self.history.add(tick, self.clock.now(), snapshot);
+            if let Some(a) = self.game.add_client(tick) {
+                self.socket.add(&a);
+            }
+            if let Some(c) = self.game.remove_client(tick) {
+                self.kick(c, KICKED);
             }
-            self.game.add_client().map(|a| self.socket.add(&a));
-            self.game.remove_client().map(|a| self.socket.remove(&a));
-            self.is_running &= self
-                .socket
-                .send_to_all(state)
-                .into_iter()
-                .map(|ex| {
-                    self.game
-                        .handle_server_event(ServerEvent::ExceptionOnSend(ex))
-                })
-                .all(|b| b);
-        }
-    }
-
-    fn update(&mut self) {
-        let _ = self
-            .socket
-            .recv()
-            .map(|(commands, from)| {
-                if self.game.allow_connect(&from) {
-                    self.is_running &=
-                        self.game
-                            .handle_command(self.update_timer.elapsed(), commands, from);
+            trace!(tick, peers = self.peers(), "state drawn");
+            for local in &self.locals {
+                local.send(state.clone(), tick);
+            }
+            self.last_state = Some((state.clone(), tick));
+            for ex in self.socket.send_to_all(state, tick) {
+                self.handle_server_event(ServerEvent::ExceptionOnSend(ex));
+            }
+        }
+    }
+
+    //Returns false if there was no datagram to read.
+    fn update(&mut self) -> bool {
+        let tick = self.tick;
+        match self.socket.recv() {
+            Ok((commands, client, address, auth)) => {
+                let _peer = peer_span!(address);
+                if let Some(payload) = auth {
+                    if !self.authenticate(client, &payload) {
+                        return true;
+                    }
+                }
+                if self.game.allow_connect(tick, client, &address) {
+                    self.record_demo(DemoEvent::Commands {
+                        tick,
+                        from: client,
+                        commands: commands.clone(),
+                    });
+                    let view = ClientView::new(
+                        self.socket.rtt(client),
+                        self.clock.now(),
+                        &self.history,
+                        self.users.get(&client),
+                    );
+                    trace!(tick, commands = commands.len(), "commands handled");
+                    self.is_running &= self.game.handle_command(
+                        tick,
+                        self.update_timer.elapsed(),
+                        commands,
+                        client,
+                        view,
+                    );
                 } else {
-                    self.socket.remove(&from);
+                    info!(client, "peer not allowed to connect");
+                    self.users.remove(&client);
+                    if let Err(ex) = self.socket.reject(client, NOT_ALLOWED) {
+                        self.handle_server_event(ServerEvent::ExceptionOnSend(ex));
+                    }
                 }
-            })
-            .map_err(|e| {
-                self.is_running &= self
-                    .game
-                    .handle_server_event(ServerEvent::ExceptionOnRecv(e))
-            });
+                true
+            }
+            Err(e) => {
+                if let ServerEvent::ExceptionOnRecvFrom((_, ex)) = &e {
+                    *self.metrics.receive_errors.entry(ex.kind()).or_insert(0) += 1;
+                }
+                let received = !is_would_block(&e);
+                self.handle_server_event(e);
+                received
+            }
+        }
+    }
+
+    //Returns false if game rejected client.
+    fn authenticate(&mut self, client: ClientId, payload: &[u8]) -> bool {
+        match self.game.authenticate(self.tick, client, payload) {
+            Ok(user) => {
+                info!(client, user = %user.name, "peer authenticated");
+                self.users.insert(client, user);
+                true
+            }
+            Err(reason) => {
+                info!(client, reason = %reason, "peer rejected");
+                if let Err(ex) = self.socket.reject(client, &reason) {
+                    self.handle_server_event(ServerEvent::ExceptionOnSend(ex));
+                }
+                false
+            }
+        }
     }
-}
 
-//trait Game {
-//    fn update(&mut self, delta_time: std::time::Duration, commands: Vec<Vec<u8>>, from_address: &str) -> Vec<u8>;
-//}
-//
-//struct GameProxy {
-//    game: std::sync::Arc<std::sync::Mutex<Game>>
-//}
-//
-//impl GameProxy {
-//    fn new(game: std::sync::Arc<std::sync::Mutex<Game>>) -> GameProxy {
-////        let mut client = crate::data_access_layer::TypedClientSocket::new("sdsf", "sdfsf").unwrap();
-////        let mut server = crate::data_access_layer::TypedServerSocket::new("asdfaf").unwrap();
-//        GameProxy { game }
-//    }
-//
-//    fn update(&mut self, delta_time: std::time::Duration, commands: Vec<Vec<u8>>, from_address: &str) -> Vec<u8> {
-//        let mut game = self.game.lock().unwrap();
-//        game.update(delta_time, commands, from_address)
-//    }
-//}
-///// Client used to communicate with server. Must be singleton in your app
-//pub struct Client {
-//    commands: mpsc::Sender<Vec<u8>>,
-//    states: mpsc::Receiver<Vec<u8>>,
-//}
-//
-//impl Client {
-//    ///Create new client and listen on port to recv packets from server_address and send its to them
-//    pub fn new(port: &str, server_address: &str) -> Result<Client, Exception> {
-//        let mut client = crate::business_logic_layer::Client::new(port, server_address)?;
-//        crate::data_access_layer::logger::init(LevelFilter::Info)?;
-//        let (tx, rx) = Client::run_process(client);
-//        Ok(Client { commands: tx, states: rx })
-//    }
-//
-//    fn run_process(mut client: crate::business_logic_layer::Client) -> (mpsc::Sender<Vec<u8>>, mpsc::Receiver<Vec<u8>>) {
-//        let (tx1, rx1) = mpsc::channel();
-//        let (tx2, rx2) = mpsc::channel();
-//        thread::spawn(move || {
-//            const SEND_TIMEOUT: time::Duration = time::Duration::from_millis(30);
-//            let mut timer = time::Instant::now();
-//            loop {
-//                if timer.elapsed() > SEND_TIMEOUT {
-//                    timer = time::Instant::now();
-//                    match rx1.try_recv() {
-//                        Ok(b) => client.send(b).map_err(|e| error!("{}", e)),
-//                        Err(mpsc::TryRecvError::Disconnected) => break,
-//                        Err(e) => Err(error!("{}", e)),
-//                    };
-//                };
-//                client.recv()
-//                    .map_err(|e|error!("{}",e))
-//                    .and_then(|b| tx2.send(b)
-//                        .map_err(|e|error!("{}",e)));
-//
-//            }
-//        });
-//        (tx1, rx2)
-//    }
-//
-//    ///Send data to server
-//    /// Don't block current thread
-//    pub fn send(&self, command: Vec<u8>) {
-//        self.commands.send(command).map_err(|e| error!("{}", e));
-//    }
-//
-//    ///Reads data fro server
-//    /// Don't block current thread
-//    /// Return None if there is no data available
-//    pub fn recv(&self) -> Option<Vec<u8>> {
-//        self.states.try_recv().ok()
-//    }
-//}
-
-//#[cfg(test)]
-//mod tests {
-//    #[test]
-//    fn it_works() {
-//        assert_eq!(1, 1);
-//    }
-//}
+    //Local players are trusted so game is not asked to allow them.
+    fn update_locals(&mut self) {
+        let tick = self.tick;
+        let received: Vec<_> = self
+            .locals
+            .iter()
+            .map(|l| (l.client(), l.recv()))
+            .collect();
+        for (client, (commands, disconnected)) in received {
+            if !commands.is_empty() && self.is_running {
+                self.record_demo(DemoEvent::Commands {
+                    tick,
+                    from: client,
+                    commands: commands.clone(),
+                });
+                let view =
+                    ClientView::new(Duration::new(0, 0), self.clock.now(), &self.history, None);
+                trace!(tick, client, commands = commands.len(), "local commands handled");
+                self.is_running &= self.game.handle_command(
+                    tick,
+                    self.update_timer.elapsed(),
+                    commands,
+                    client,
+                    view,
+                );
+            }
+            if let Some(reason) = disconnected {
+                self.locals.retain(|l| l.client() != client);
+                self.handle_server_event(ServerEvent::ClientDisconnected((client, reason)));
+            }
+        }
+    }
+
+    fn handle_server_event(&mut self, event: ServerEvent) {
+        if let ServerEvent::ClientDisconnected((client, _)) = &event {
+            self.users.remove(client);
+        }
+        match &event {
+            e if is_would_block(e) => {}
+            ServerEvent::ExceptionOnRecvFrom((_peer, _e)) => {
+                debug!(peer = %_peer, error = %_e, "command from peer dropped")
+            }
+            ServerEvent::ExceptionOnSend((_peer, _e)) => {
+                warn!(peer = %_peer, error = %_e, "state not sent to peer")
+            }
+            ServerEvent::ClientDisconnected((_client, _reason)) => {
+                info!(client = _client, reason = %_reason, "peer disconnected")
+            }
+            ServerEvent::ClientReconnected(_client) => info!(client = _client, "peer reconnected"),
+            ServerEvent::ClientQueued(_peer) => info!(peer = %_peer, "peer waits for free place"),
+            ServerEvent::ServerFull(_peer) => info!(peer = %_peer, "peer refused by full server"),
+            _ => warn!(event = ?event, "server event"),
+        }
+        self.is_running &= self.game.handle_server_event(self.tick, event);
+    }
+
+    //Like capture demo stops on first error so game gets it once.
+    fn record_demo(&mut self, event: DemoEvent<T::Command, T::State>) {
+        if let Some(Err(e)) = self.demo.as_mut().map(|d| d.record(&event)) {
+            warn!(error = %e, "demo recording stopped");
+            self.demo = None;
+            self.handle_server_event(ServerEvent::ExceptionOnRecord(e));
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use crate::{ClientId, Exception, Tick, CLIENT_DROPPED};
+use std::collections::VecDeque;
+use std::io;
+use std::sync::{Arc, Mutex};
+
+const MAX_LOCAL_STATES: usize = 64;
+
+struct Link<C, S> {
+    commands: Vec<C>,
+    states: VecDeque<(S, Tick)>,
+    //Reason of server.
+    closed: Option<String>,
+    //Reason of client.
+    disconnected: Option<String>,
+}
+
+///Player of listen server in same process as [`crate::GameServer`].
+/// Commands go to game and states come from it without network.
+/// Game sees it as any other client with its own [`ClientId`].
+/// Create it with [`crate::GameServer::connect_local`].
+pub struct LocalClient<C, S> {
+    client: ClientId,
+    link: Arc<Mutex<Link<C, S>>>,
+    tick: Tick,
+    is_disconnected: bool,
+}
+
+impl<C, S> LocalClient<C, S> {
+    ///Id game gets with commands of this client.
+    pub fn id(&self) -> ClientId {
+        self.client
+    }
+
+    ///Sends command to game on next circle of server.
+    /// Return [`Exception::Disconnected`] if server closed.
+    pub fn send(&mut self, command: C) -> Result<(), Exception> {
+        let mut link = self.link.lock().unwrap();
+        if let Some(reason) = &link.closed {
+            return Err(Exception::Disconnected(reason.clone()));
+        }
+        link.commands.push(command);
+        Ok(())
+    }
+
+    ///Reads states in order they were drawn.
+    /// Return [`Exception`] with [`std::io::ErrorKind::WouldBlock`] if there is no state.
+    /// Return [`Exception::Disconnected`] if server closed and all states are read.
+    pub fn recv(&mut self) -> Result<S, Exception> {
+        let mut link = self.link.lock().unwrap();
+        match link.states.pop_front() {
+            Some((state, tick)) => {
+                self.tick = tick;
+                Ok(state)
+            }
+            None => match &link.closed {
+                Some(reason) => Err(Exception::Disconnected(reason.clone())),
+                None => Err(io::Error::from(io::ErrorKind::WouldBlock).into()),
+            },
+        }
+    }
+
+    ///Server tick on which last received state was drawn.
+    pub fn tick(&self) -> Tick {
+        self.tick
+    }
+
+    ///Tells server to remove this client with reason.
+    /// Called on drop if it was not called before.
+    pub fn disconnect(&mut self, reason: &str) {
+        self.is_disconnected = true;
+        self.link.lock().unwrap().disconnected = Some(reason.to_owned());
+    }
+}
+
+impl<C, S> Drop for LocalClient<C, S> {
+    fn drop(&mut self) {
+        if !self.is_disconnected {
+            self.disconnect(CLIENT_DROPPED);
+        }
+    }
+}
+
+///Side of [`LocalClient`] owned by server.
+pub struct LocalPeer<C, S> {
+    client: ClientId,
+    link: Arc<Mutex<Link<C, S>>>,
+}
+
+impl<C, S> LocalPeer<C, S> {
+    pub fn new(client: ClientId) -> (LocalPeer<C, S>, LocalClient<C, S>) {
+        let link = Arc::new(Mutex::new(Link {
+            commands: Vec::new(),
+            states: VecDeque::new(),
+            closed: None,
+            disconnected: None,
+        }));
+        let peer = LocalPeer {
+            client,
+            link: link.clone(),
+        };
+        let client = LocalClient {
+            client,
+            link,
+            tick: 0,
+            is_disconnected: false,
+        };
+        (peer, client)
+    }
+
+    pub fn client(&self) -> ClientId {
+        self.client
+    }
+
+    ///Takes commands sent from last call and reason if client disconnected.
+    pub fn recv(&self) -> (Vec<C>, Option<String>) {
+        let mut link = self.link.lock().unwrap();
+        let commands = std::mem::take(&mut link.commands);
+        (commands, link.disconnected.take())
+    }
+
+    ///Client not reading states keeps only recent ones.
+    pub fn send(&self, state: S, tick: Tick) {
+        let mut link = self.link.lock().unwrap();
+        if link.states.len() >= MAX_LOCAL_STATES {
+            link.states.pop_front();
+        }
+        link.states.push_back((state, tick));
+    }
+
+    pub fn close(&self, reason: &str) {
+        self.link.lock().unwrap().closed = Some(reason.to_owned());
+    }
+}
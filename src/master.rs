@@ -0,0 +1,146 @@
+use crate::business_logic_layer as bll;
+use crate::data_access_layer::TypedServerSocket;
+use crate::entities::{ListedServer, Master, MasterPacket, ServerFilter, ServerStatus};
+use crate::query::ask;
+use crate::{
+    Bincode, Clock, Codec, Exception, ShutdownHandle, Transport, MAX_FLUSHED_PACKETS,
+    MAX_QUERIES_PER_PERIOD, QUERY_PERIOD_IN_MILLIS, REGISTRATION_TIMEOUT_IN_MILLIS,
+};
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+const IDLE_SLEEP_IN_MILLIS: u64 = 1;
+//Answer must fit in one datagram.
+const MAX_LISTED_SERVERS: usize = 256;
+
+///Lists public game servers to clients.
+/// Game servers send heartbeat with status to it and are forgotten when they stop.
+/// Create it with [`crate::GameServerBuilder::build_master`].
+pub struct MasterServer<K: Codec = Bincode, N: Transport = UdpSocket> {
+    socket: TypedServerSocket<K, N>,
+    registry: bll::registry::Registry<SocketAddr, ServerStatus>,
+    limiter: bll::query::RateLimiter,
+    shutdown: ShutdownHandle,
+}
+
+impl<K: Codec, N: Transport> MasterServer<K, N> {
+    pub(crate) fn new(transport: N, codec: K, clock: Arc<dyn Clock>) -> MasterServer<K, N> {
+        MasterServer {
+            socket: TypedServerSocket::new(transport, codec),
+            registry: bll::registry::Registry::new(
+                Duration::from_millis(REGISTRATION_TIMEOUT_IN_MILLIS),
+                clock.clone(),
+            ),
+            limiter: bll::query::RateLimiter::new(
+                MAX_QUERIES_PER_PERIOD,
+                Duration::from_millis(QUERY_PERIOD_IN_MILLIS),
+                clock,
+            ),
+            shutdown: ShutdownHandle::default(),
+        }
+    }
+
+    ///Registered game servers.
+    pub fn servers(&self) -> Vec<ListedServer> {
+        self.registry
+            .entries()
+            .into_iter()
+            .map(|(address, status)| ListedServer { address, status })
+            .collect()
+    }
+
+    ///Handle to stop server from other thread.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    ///Handles requests until [`ShutdownHandle`] stops server.
+    /// Blocks current thread.
+    pub fn run(&mut self) {
+        while !self.shutdown.is_shutdown() {
+            if !self.run_once() {
+                thread::sleep(Duration::from_millis(IDLE_SLEEP_IN_MILLIS));
+            }
+        }
+    }
+
+    ///Handles received requests and forgets servers without heartbeat.
+    /// Returns false if there was no request.
+    pub fn run_once(&mut self) -> bool {
+        self.registry.expire();
+        self.limiter.expire();
+        for i in 0..MAX_FLUSHED_PACKETS {
+            match self.socket.read::<MasterPacket>() {
+                Ok((Ok(packet), from)) => self.handle(packet, from),
+                Ok((Err(_e), _from)) => debug!(peer = %_from, error = %_e, "request dropped"),
+                Err(Exception::IoError(ref e)) if e.kind() == io::ErrorKind::WouldBlock => {
+                    return i > 0
+                }
+                Err(_e) => warn!(error = %_e, "request not received"),
+            }
+        }
+        true
+    }
+
+    fn handle(&mut self, packet: MasterPacket, from: SocketAddr) {
+        let _peer = peer_span!(from);
+        match bll::envelope::open(packet) {
+            Ok(Master::Heartbeat(status)) => {
+                trace!(players = status.players, "heartbeat received");
+                self.registry.register(from, status)
+            }
+            Ok(Master::List(request, filter)) => {
+                if !self.limiter.allow(from.ip()) {
+                    trace!("list request over limit ignored");
+                    return;
+                }
+                let servers: Vec<ListedServer> = self
+                    .servers()
+                    .into_iter()
+                    .filter(|s| bll::master::matches(&filter, &s.status))
+                    .take(MAX_LISTED_SERVERS)
+                    .collect();
+                debug!(servers = servers.len(), "servers listed");
+                let answer = bll::envelope::packet(Master::Servers(request, servers));
+                if let Err(_e) = self.socket.write(&from, &answer) {
+                    warn!(error = %_e, "servers not listed");
+                }
+            }
+            Ok(_) => {}
+            Err(_e) => debug!(error = %_e, "request dropped"),
+        }
+    }
+}
+
+///Sends list request to master server again until it answers or timeout.
+pub fn list<K: Codec, N: Transport>(
+    transport: &N,
+    codec: &K,
+    clock: Arc<dyn Clock>,
+    master: SocketAddr,
+    filter: ServerFilter,
+    timeout: Duration,
+) -> Result<Vec<ListedServer>, Exception> {
+    let request = bll::new_token();
+    let packet = bll::envelope::packet(Master::List(request, filter));
+    let answer = |bytes: &[u8]| match codec.deserialize::<MasterPacket>(bytes) {
+        Ok(packet) => match bll::envelope::open(packet) {
+            Ok(Master::Servers(r, servers)) if r == request => Some(servers),
+            _ => None,
+        },
+        _ => None,
+    };
+    let answer = ask(
+        transport,
+        clock,
+        master,
+        timeout,
+        &codec.serialize(&packet)?,
+        answer,
+    )?;
+    answer
+        .ok_or_else(|| io::Error::new(io::ErrorKind::TimedOut, "master server don't answer").into())
+}
@@ -0,0 +1,55 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+///Counters and gauges of [`crate::GameServer`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Metrics {
+    ///Clients server sends states to.
+    pub peers: usize,
+    pub packets_in: u64,
+    pub bytes_in: u64,
+    pub packets_out: u64,
+    pub bytes_out: u64,
+    ///Errors on receive of commands from clients by [`crate::Exception::kind`].
+    /// Not only decode errors but not ordered and not valid packets too.
+    pub receive_errors: BTreeMap<&'static str, u64>,
+    ///Time of last [`crate::Game::update`].
+    pub tick_duration: Duration,
+    ///Commands received after newer ones from same client.
+    /// Resent by client because server lost them or just reordered on the way.
+    pub out_of_order: u64,
+}
+
+impl Metrics {
+    ///Formats metrics in Prometheus text format.
+    pub fn to_prometheus(&self) -> String {
+        let mut text = String::new();
+        let mut metric = |name: &str, kind: &str, value: String| {
+            text.push_str(&format!("# TYPE victorem_{} {}\n", name, kind));
+            text.push_str(&format!("victorem_{} {}\n", name, value));
+        };
+        metric("peers", "gauge", self.peers.to_string());
+        metric("packets_in_total", "counter", self.packets_in.to_string());
+        metric("bytes_in_total", "counter", self.bytes_in.to_string());
+        metric("packets_out_total", "counter", self.packets_out.to_string());
+        metric("bytes_out_total", "counter", self.bytes_out.to_string());
+        metric(
+            "tick_duration_seconds",
+            "gauge",
+            self.tick_duration.as_secs_f64().to_string(),
+        );
+        metric(
+            "out_of_order_total",
+            "counter",
+            self.out_of_order.to_string(),
+        );
+        text.push_str("# TYPE victorem_receive_errors_total counter\n");
+        for (kind, count) in &self.receive_errors {
+            text.push_str(&format!(
+                "victorem_receive_errors_total{{kind=\"{}\"}} {}\n",
+                kind, count
+            ));
+        }
+        text
+    }
+}
@@ -0,0 +1,555 @@
+use crate::data_access_layer::room::{RoomInbox, Router};
+use crate::data_access_layer::Traffic;
+use crate::entities::{ClientPacket, ServerInfo, ServerStatus};
+use crate::{
+    is_would_block, Capacity, ClientId, Clock, Codec, Exception, Message, Received,
+    ServerEvent, ServerSocket, Tick, Timeouts, Transport, MAX_FLUSHED_PACKETS,
+};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+const WORKER_WAIT_IN_MILLIS: u64 = 1;
+
+///Clients of [`crate::GameServer`].
+/// Served on thread of server or sharded across worker threads.
+pub enum Peers<C: Message, S, K: Codec, N: Transport> {
+    Local(Box<ServerSocket<C, K, N>>),
+    Sharded(Box<Shards<C, S, K, N>>),
+}
+
+impl<C: Message, S: Message, K: Codec, N: Transport> Peers<C, S, K, N> {
+    pub fn recv(&mut self) -> Received<C> {
+        match self {
+            Peers::Local(socket) => socket.recv(),
+            Peers::Sharded(shards) => shards.recv(),
+        }
+    }
+
+    pub fn add(&mut self, address: &SocketAddr) {
+        match self {
+            Peers::Local(socket) => {
+                socket.add(address);
+            }
+            Peers::Sharded(shards) => {
+                let workers = shards.workers.len();
+                let index = *shards
+                    .routes
+                    .entry(*address)
+                    .or_insert_with(|| shard(address, workers));
+                shards.workers[index].send(ToWorker::Add(*address))
+            }
+        }
+    }
+
+    ///Id for client without address like local player.
+    pub fn new_id(&self) -> ClientId {
+        match self {
+            Peers::Local(socket) => socket.new_id(),
+            Peers::Sharded(shards) => shards.ids.fetch_add(1, Ordering::Relaxed) + 1,
+        }
+    }
+
+    ///Worker reports error of send later as event.
+    pub fn reject(
+        &mut self,
+        client: ClientId,
+        reason: &str,
+    ) -> Result<(), (SocketAddr, Exception)> {
+        match self {
+            Peers::Local(socket) => socket.reject(client, reason),
+            Peers::Sharded(shards) => {
+                let workers = shards.forget(client);
+                //Ids are not reused so commands sent by worker before reject are dropped by id
+                // until every worker acknowledged reject.
+                shards.rejected.insert(client, workers.len());
+                for worker in workers {
+                    shards.workers[worker].send(ToWorker::Reject(client, reason.to_owned()));
+                }
+                Ok(())
+            }
+        }
+    }
+
+    ///Workers get only changed info.
+    pub fn set_info(&mut self, info: ServerInfo) {
+        match self {
+            Peers::Local(socket) => socket.info = Some(info),
+            Peers::Sharded(shards) => {
+                if shards.info.as_ref() != Some(&info) {
+                    for worker in &shards.workers {
+                        worker.send(ToWorker::Info(info.clone()));
+                    }
+                    shards.info = Some(info);
+                }
+            }
+        }
+    }
+
+    ///Workers get only changed status.
+    pub fn set_status(&mut self, status: ServerStatus) {
+        match self {
+            Peers::Local(socket) => socket.status = Some(status),
+            Peers::Sharded(shards) => {
+                if shards.status.as_ref() != Some(&status) {
+                    for worker in &shards.workers {
+                        worker.send(ToWorker::Status(status.clone()));
+                    }
+                    shards.status = Some(status);
+                }
+            }
+        }
+    }
+
+    ///Workers report expired sessions later as events.
+    pub fn maintain(&mut self) -> Vec<ServerEvent> {
+        match self {
+            Peers::Local(socket) => socket.maintain(),
+            Peers::Sharded(shards) => {
+                for worker in &shards.workers {
+                    worker.send(ToWorker::Maintain);
+                }
+                Vec::new()
+            }
+        }
+    }
+
+    ///Workers report clients with their first commands so client not heard yet is unknown.
+    pub fn contains(&self, client: ClientId) -> bool {
+        match self {
+            Peers::Local(socket) => socket.contains(client),
+            Peers::Sharded(shards) => shards.owners.contains_key(&client),
+        }
+    }
+
+    pub fn client(&self, address: &SocketAddr) -> Option<ClientId> {
+        match self {
+            Peers::Local(socket) => socket.client(address),
+            Peers::Sharded(shards) => shards.clients.get(address).copied(),
+        }
+    }
+
+    pub fn rtt(&self, client: ClientId) -> Duration {
+        match self {
+            Peers::Local(socket) => socket.rtt(client),
+            Peers::Sharded(shards) => shards
+                .rtts
+                .get(&client)
+                .copied()
+                .unwrap_or_else(|| Duration::new(0, 0)),
+        }
+    }
+
+    ///Workers report errors later as events.
+    pub fn send_to_all(&mut self, state: S, tick: Tick) -> Vec<(SocketAddr, Exception)> {
+        match self {
+            Peers::Local(socket) => socket.send_to_all(state, tick),
+            Peers::Sharded(shards) => {
+                for worker in &shards.workers {
+                    worker.send(ToWorker::State(state.clone(), tick));
+                }
+                Vec::new()
+            }
+        }
+    }
+
+    ///Stops workers after they sent disconnect.
+    pub fn disconnect_all(&mut self, reason: &str) -> Vec<(SocketAddr, Exception)> {
+        match self {
+            Peers::Local(socket) => socket.disconnect_all(reason),
+            Peers::Sharded(shards) => shards.stop(reason),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Peers::Local(socket) => socket.servers.len(),
+            Peers::Sharded(shards) => shards.workers.iter().map(|w| w.stats.peers).sum(),
+        }
+    }
+
+    pub fn traffic(&self) -> Traffic {
+        match self {
+            Peers::Local(socket) => socket.socket.traffic(),
+            Peers::Sharded(shards) => shards.workers.iter().fold(Traffic::default(), |t, w| {
+                let s = w.stats.traffic;
+                Traffic {
+                    packets_in: t.packets_in + s.packets_in,
+                    bytes_in: t.bytes_in + s.bytes_in,
+                    packets_out: t.packets_out + s.packets_out,
+                    bytes_out: t.bytes_out + s.bytes_out,
+                }
+            }),
+        }
+    }
+
+    pub fn out_of_order(&self) -> u64 {
+        match self {
+            Peers::Local(socket) => socket.out_of_order,
+            Peers::Sharded(shards) => shards.workers.iter().map(|w| w.stats.out_of_order).sum(),
+        }
+    }
+
+    ///Workers report it with stats after each state.
+    pub fn is_waiting(&self) -> bool {
+        match self {
+            Peers::Local(socket) => socket.is_waiting(),
+            Peers::Sharded(shards) => shards.workers.iter().any(|w| w.stats.is_waiting),
+        }
+    }
+}
+
+enum ToWorker<S> {
+    State(S, Tick),
+    Add(SocketAddr),
+    Reject(ClientId, String),
+    Capacity(Capacity),
+    Maintain,
+    Info(ServerInfo),
+    Status(ServerStatus),
+    Disconnect(String),
+}
+
+enum FromWorker<C> {
+    Commands(usize, Vec<C>, ClientId, SocketAddr, Duration, Option<Vec<u8>>),
+    Event(ServerEvent),
+    Rejected(ClientId),
+    Stats(usize, Stats),
+    Stopped(Vec<(SocketAddr, Exception)>),
+}
+
+//Queue instead of channel so server borrowing data can be dropped after it.
+type Mailbox<T> = Arc<Mutex<VecDeque<T>>>;
+
+#[derive(Clone, Copy, Default)]
+struct Stats {
+    peers: usize,
+    traffic: Traffic,
+    out_of_order: u64,
+    is_waiting: bool,
+}
+
+struct Worker<S> {
+    mailbox: Mailbox<ToWorker<S>>,
+    inbox: RoomInbox,
+    thread: Option<JoinHandle<()>>,
+    stats: Stats,
+}
+
+impl<S> Worker<S> {
+    fn send(&self, message: ToWorker<S>) {
+        self.mailbox.lock().unwrap().push_back(message);
+    }
+}
+
+///Workers each owning sessions of part of clients.
+/// Game stays on thread of server. Workers only decode, order and send packets.
+/// Clients are sharded by resume token or session key so client from new address stays on same worker.
+pub struct Shards<C, S, K: Codec, N: Transport> {
+    router: Router<N>,
+    codec: K,
+    workers: Vec<Worker<S>>,
+    mailbox: Mailbox<FromWorker<C>>,
+    routes: HashMap<SocketAddr, usize>,
+    //Worker owning session of admitted client.
+    owners: HashMap<ClientId, usize>,
+    addresses: HashMap<ClientId, SocketAddr>,
+    clients: HashMap<SocketAddr, ClientId>,
+    rtts: HashMap<ClientId, Duration>,
+    ids: Arc<AtomicU32>,
+    info: Option<ServerInfo>,
+    status: Option<ServerStatus>,
+    //Rejected clients with count of workers that not yet acknowledged reject.
+    rejected: HashMap<ClientId, usize>,
+}
+
+impl<C, S, K, N> Shards<C, S, K, N>
+where
+    C: Message + Send + 'static,
+    S: Message + Send + 'static,
+    K: Codec + Clone + Send + 'static,
+    N: Transport + Send + Sync + 'static,
+{
+    pub fn new(
+        transport: N,
+        codec: K,
+        workers: usize,
+        clock: Arc<dyn Clock>,
+        timeouts: Timeouts,
+        rendezvous: Option<(SocketAddr, String)>,
+        master: Option<SocketAddr>,
+    ) -> Shards<C, S, K, N> {
+        let router = Router::new(transport);
+        let mailbox = Mailbox::default();
+        let ids = Arc::new(AtomicU32::new(0));
+        let connected = Arc::new(AtomicUsize::new(0));
+        let workers = workers.max(1);
+        let workers = (0..workers)
+            .map(|index| {
+                let transport = router.room();
+                let inbox = transport.inbox();
+                let mut socket = ServerSocket::new(transport, codec.clone(), clock.clone());
+                socket.ids = Arc::clone(&ids);
+                socket.connected = Arc::clone(&connected);
+                socket.shard = Some((index, workers));
+                socket.timeouts = timeouts;
+                //Rendezvous packets are routed by address so one worker talks to rendezvous.
+                if let Some((address, name)) = &rendezvous {
+                    if shard(address, workers) == index {
+                        socket.register_on(*address, name.clone());
+                    }
+                }
+                //One worker is enough. Every worker gets status of whole server.
+                if let Some(address) = master {
+                    if shard(&address, workers) == index {
+                        socket.heartbeat_to(address);
+                    }
+                }
+                let to_worker = Mailbox::default();
+                let (from_server, to_server) = (Arc::clone(&to_worker), Arc::clone(&mailbox));
+                let thread = thread::spawn(move || work(index, socket, from_server, to_server));
+                Worker {
+                    mailbox: to_worker,
+                    inbox,
+                    thread: Some(thread),
+                    stats: Stats::default(),
+                }
+            })
+            .collect();
+        Shards {
+            router,
+            codec,
+            workers,
+            mailbox,
+            routes: HashMap::new(),
+            owners: HashMap::new(),
+            addresses: HashMap::new(),
+            clients: HashMap::new(),
+            rtts: HashMap::new(),
+            ids,
+            info: None,
+            status: None,
+            rejected: HashMap::new(),
+        }
+    }
+
+    ///Workers share count of clients so limit is for whole server.
+    pub fn set_capacity(&mut self, capacity: Capacity) {
+        for worker in &self.workers {
+            worker.send(ToWorker::Capacity(capacity));
+        }
+    }
+}
+
+pub fn shard(value: &impl Hash, workers: usize) -> usize {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    (hasher.finish() % workers as u64) as usize
+}
+
+impl<C: Message, S, K: Codec, N: Transport> Shards<C, S, K, N> {
+    //Datagrams from address are decoded here until worker admits its client.
+    // Others go to worker of address.
+    fn route(&self, datagram: &[u8], from: SocketAddr) -> usize {
+        if let Some(index) = self.routes.get(&from) {
+            return *index;
+        }
+        match self.codec.deserialize::<ClientPacket<C>>(datagram) {
+            Ok(packet) => {
+                //Worker gives tokens routed to itself so token finds worker of session.
+                let workers = self.workers.len();
+                match &packet {
+                    ClientPacket::Command(command) => match command.resume_token {
+                        Some(token) => shard(&token, workers),
+                        None => shard(&command.session_key, workers),
+                    },
+                    ClientPacket::Disconnect(packet) => shard(&packet.session_key, workers),
+                    //Worker of rendezvous address knows rendezvous.
+                    // Punch of client don't choose worker for its commands.
+                    ClientPacket::Rendezvous(_) => shard(&from, workers),
+                    //Every worker knows info and status.
+                    ClientPacket::Discovery(_) | ClientPacket::Query(_) => shard(&from, workers),
+                }
+            }
+            Err(_) => shard(&from, self.workers.len()),
+        }
+    }
+
+    //Returns workers that may know client. All of them if client was not admitted yet.
+    fn forget(&mut self, client: ClientId) -> Vec<usize> {
+        self.rtts.remove(&client);
+        if let Some(address) = self.addresses.remove(&client) {
+            self.routes.remove(&address);
+            self.clients.remove(&address);
+        }
+        match self.owners.remove(&client) {
+            Some(worker) => vec![worker],
+            None => (0..self.workers.len()).collect(),
+        }
+    }
+
+    fn recv(&mut self) -> Received<C> {
+        for _ in 0..MAX_FLUSHED_PACKETS {
+            match self.router.recv() {
+                Ok((datagram, from)) => {
+                    let index = self.route(&datagram, from);
+                    self.workers[index].inbox.push(datagram, from)
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => return Err(ServerEvent::ExceptionOnRecv(e.into())),
+            }
+        }
+        loop {
+            let message = self.mailbox.lock().unwrap().pop_front();
+            match message {
+                Some(FromWorker::Commands(_, _, client, _, _, _))
+                    if self.rejected.contains_key(&client) =>
+                {
+                    trace!(client, "commands of rejected peer dropped");
+                }
+                //Route is kept only for admitted clients so refused and queued don't fill it.
+                Some(FromWorker::Commands(index, commands, client, address, rtt, auth)) => {
+                    self.routes.insert(address, index);
+                    self.owners.insert(client, index);
+                    self.rtts.insert(client, rtt);
+                    self.clients.insert(address, client);
+                    if let Some(old) = self.addresses.insert(client, address) {
+                        if old != address {
+                            self.routes.remove(&old);
+                            self.clients.remove(&old);
+                        }
+                    }
+                    return Ok((commands, client, address, auth));
+                }
+                Some(FromWorker::Event(event)) => {
+                    if let ServerEvent::ClientDisconnected((client, _)) = &event {
+                        self.forget(*client);
+                    }
+                    return Err(event);
+                }
+                Some(FromWorker::Rejected(client)) => {
+                    if let Some(workers) = self.rejected.get_mut(&client) {
+                        *workers -= 1;
+                        if *workers == 0 {
+                            self.rejected.remove(&client);
+                        }
+                    }
+                }
+                Some(FromWorker::Stats(index, stats)) => self.workers[index].stats = stats,
+                Some(FromWorker::Stopped(_)) => {}
+                None => {
+                    return Err(ServerEvent::ExceptionOnRecv(
+                        io::Error::from(io::ErrorKind::WouldBlock).into(),
+                    ))
+                }
+            }
+        }
+    }
+
+    fn stop(&mut self, reason: &str) -> Vec<(SocketAddr, Exception)> {
+        for worker in &self.workers {
+            worker.send(ToWorker::Disconnect(reason.to_owned()));
+        }
+        for worker in &mut self.workers {
+            if let Some(thread) = worker.thread.take() {
+                if thread.join().is_err() {
+                    warn!("worker panicked");
+                }
+            }
+        }
+        let mut exceptions = Vec::new();
+        for message in self.mailbox.lock().unwrap().drain(..) {
+            match message {
+                FromWorker::Stopped(mut e) => exceptions.append(&mut e),
+                FromWorker::Stats(index, stats) => self.workers[index].stats = stats,
+                _ => {}
+            }
+        }
+        exceptions
+    }
+}
+
+fn work<C: Message, S: Message, K: Codec, N: Transport>(
+    index: usize,
+    mut socket: ServerSocket<C, K, N>,
+    from_server: Mailbox<ToWorker<S>>,
+    to_server: Mailbox<FromWorker<C>>,
+) {
+    let stats = |socket: &ServerSocket<C, K, N>| {
+        FromWorker::Stats(
+            index,
+            Stats {
+                peers: socket.servers.len(),
+                traffic: socket.socket.traffic(),
+                out_of_order: socket.out_of_order,
+                is_waiting: socket.is_waiting(),
+            },
+        )
+    };
+    //Server dropped so there is no one to work for.
+    while Arc::strong_count(&from_server) > 1 {
+        let mut messages = Vec::new();
+        for _ in 0..MAX_FLUSHED_PACKETS {
+            match socket.recv() {
+                Ok((commands, client, address, auth)) => {
+                    let rtt = socket.rtt(client);
+                    let commands = FromWorker::Commands(index, commands, client, address, rtt, auth);
+                    messages.push(commands);
+                }
+                Err(ref e) if is_would_block(e) => break,
+                Err(e) => messages.push(FromWorker::Event(e)),
+            }
+        }
+        let orders: Vec<_> = from_server.lock().unwrap().drain(..).collect();
+        let is_idle = messages.is_empty() && orders.is_empty();
+        let mut is_stopped = false;
+        for order in orders {
+            match order {
+                ToWorker::State(state, tick) => {
+                    for ex in socket.send_to_all(state, tick) {
+                        messages.push(FromWorker::Event(ServerEvent::ExceptionOnSend(ex)));
+                    }
+                    messages.push(stats(&socket));
+                }
+                ToWorker::Add(address) => {
+                    socket.add(&address);
+                }
+                ToWorker::Capacity(capacity) => socket.set_capacity(capacity),
+                ToWorker::Reject(client, reason) => {
+                    if let Err(ex) = socket.reject(client, &reason) {
+                        messages.push(FromWorker::Event(ServerEvent::ExceptionOnSend(ex)));
+                    }
+                    messages.push(FromWorker::Rejected(client));
+                }
+                ToWorker::Maintain => {
+                    messages.extend(socket.maintain().into_iter().map(FromWorker::Event))
+                }
+                ToWorker::Info(info) => socket.info = Some(info),
+                ToWorker::Status(status) => socket.status = Some(status),
+                ToWorker::Disconnect(reason) => {
+                    let exceptions = socket.disconnect_all(&reason);
+                    messages.push(stats(&socket));
+                    messages.push(FromWorker::Stopped(exceptions));
+                    is_stopped = true;
+                    break;
+                }
+            }
+        }
+        to_server.lock().unwrap().extend(messages);
+        if is_stopped {
+            return;
+        }
+        //Clock of server so manual clock is not moved by workers and wakes them.
+        if is_idle {
+            socket
+                .clock
+                .wait(Duration::from_millis(WORKER_WAIT_IN_MILLIS));
+        }
+    }
+}
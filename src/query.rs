@@ -0,0 +1,82 @@
+use crate::business_logic_layer as bll;
+use crate::entities::{ClientPacket, Query, ServerPacket, ServerStatus};
+use crate::{
+    ClientSocketBuilder, Clock, Codec, Exception, Transport, CONNECT_PERIOD_IN_MILLIS,
+    MAX_DATAGRAM_SIZE, QUERY_TIMEOUT_IN_MILLIS,
+};
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::Arc;
+use std::time::Duration;
+
+const IDLE_SLEEP_IN_MILLIS: u64 = 1;
+
+///Asks status of server listening address without connecting to it.
+/// Waits answer for about second.
+/// Use [`ClientSocketBuilder::query_server`] for other transport, codec or timeout.
+pub fn query_server(address: impl ToSocketAddrs) -> Result<ServerStatus, Exception> {
+    ClientSocketBuilder::new(0)
+        .query_server(address, Duration::from_millis(QUERY_TIMEOUT_IN_MILLIS))
+}
+
+///Sends request to address again until it answers or timeout.
+pub fn query<K: Codec, N: Transport>(
+    transport: &N,
+    codec: &K,
+    clock: Arc<dyn Clock>,
+    address: SocketAddr,
+    timeout: Duration,
+) -> Result<ServerStatus, Exception> {
+    let request = bll::new_token();
+    let packet = ClientPacket::<()>::Query(bll::envelope::packet(Query::Request(request)));
+    let answer = |bytes: &[u8]| match codec.deserialize::<ServerPacket<()>>(bytes) {
+        Ok(ServerPacket::Query(packet)) => match bll::envelope::open(packet) {
+            Ok(Query::Status(r, status)) if r == request => Some(status),
+            _ => None,
+        },
+        _ => None,
+    };
+    let answer = ask(
+        transport,
+        clock,
+        address,
+        timeout,
+        &codec.serialize(&packet)?,
+        answer,
+    )?;
+    answer
+        .ok_or_else(|| io::Error::new(io::ErrorKind::TimedOut, "server don't answer query").into())
+}
+
+//Returns None on timeout. Datagrams not from address or not answer are ignored.
+pub fn ask<R, N: Transport>(
+    transport: &N,
+    clock: Arc<dyn Clock>,
+    address: SocketAddr,
+    timeout: Duration,
+    request: &[u8],
+    answer: impl Fn(&[u8]) -> Option<R>,
+) -> Result<Option<R>, Exception> {
+    transport.send_to(request, &address)?;
+    let sent = clock.now();
+    let mut timer = bll::timer::WaitTimer::new(CONNECT_PERIOD_IN_MILLIS, clock.clone());
+    let mut buffer = vec![0u8; MAX_DATAGRAM_SIZE];
+    while clock.now() - sent < timeout {
+        match transport.recv_from(&mut buffer) {
+            Ok((size, from)) if from == address => {
+                if let Some(r) = answer(&buffer[..size]) {
+                    return Ok(Some(r));
+                }
+            }
+            Ok(_) => {}
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                if timer.continue_execution() {
+                    transport.send_to(request, &address)?;
+                }
+                clock.sleep(Duration::from_millis(IDLE_SLEEP_IN_MILLIS));
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(None)
+}
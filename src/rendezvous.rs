@@ -0,0 +1,110 @@
+use crate::business_logic_layer as bll;
+use crate::data_access_layer::TypedServerSocket;
+use crate::entities::{ClientPacket, Rendezvous, RendezvousPacket, ServerPacket};
+use crate::{
+    Bincode, Clock, Codec, Exception, ShutdownHandle, Transport, MAX_FLUSHED_PACKETS,
+    REGISTRATION_TIMEOUT_IN_MILLIS,
+};
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+const IDLE_SLEEP_IN_MILLIS: u64 = 1;
+
+///Introduces clients to game servers behind NAT so they can punch holes to each other.
+/// Run it on host with public address.
+/// Create it with [`crate::GameServerBuilder::build_rendezvous`].
+pub struct RendezvousServer<K: Codec = Bincode, N: Transport = UdpSocket> {
+    socket: TypedServerSocket<K, N>,
+    registry: bll::registry::Registry<String, SocketAddr>,
+    shutdown: ShutdownHandle,
+}
+
+impl<K: Codec, N: Transport> RendezvousServer<K, N> {
+    pub(crate) fn new(transport: N, codec: K, clock: Arc<dyn Clock>) -> RendezvousServer<K, N> {
+        RendezvousServer {
+            socket: TypedServerSocket::new(transport, codec),
+            registry: bll::registry::Registry::new(
+                Duration::from_millis(REGISTRATION_TIMEOUT_IN_MILLIS),
+                clock,
+            ),
+            shutdown: ShutdownHandle::default(),
+        }
+    }
+
+    ///Names of registered game servers.
+    pub fn servers(&self) -> Vec<String> {
+        self.registry
+            .entries()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect()
+    }
+
+    ///Handle to stop server from other thread.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    ///Handles requests until [`ShutdownHandle`] stops server.
+    /// Blocks current thread.
+    pub fn run(&mut self) {
+        while !self.shutdown.is_shutdown() {
+            if !self.run_once() {
+                thread::sleep(Duration::from_millis(IDLE_SLEEP_IN_MILLIS));
+            }
+        }
+    }
+
+    ///Handles received requests and forgets servers not registered again.
+    /// Returns false if there was no request.
+    pub fn run_once(&mut self) -> bool {
+        self.registry.expire();
+        for i in 0..MAX_FLUSHED_PACKETS {
+            match self.socket.read::<RendezvousPacket>() {
+                Ok((Ok(packet), from)) => self.handle(packet, from),
+                Ok((Err(_e), _from)) => debug!(peer = %_from, error = %_e, "request dropped"),
+                Err(Exception::IoError(ref e)) if e.kind() == io::ErrorKind::WouldBlock => {
+                    return i > 0
+                }
+                Err(_e) => warn!(error = %_e, "request not received"),
+            }
+        }
+        true
+    }
+
+    fn handle(&mut self, packet: RendezvousPacket, from: SocketAddr) {
+        let _peer = peer_span!(from);
+        match bll::envelope::open(packet) {
+            Ok(Rendezvous::Register(name)) => {
+                trace!(name = %name, "server registered");
+                self.registry.register(name, from)
+            }
+            Ok(Rendezvous::Connect(name)) => {
+                let server = self.registry.find(&name);
+                if let Some(server) = server {
+                    if let Err(_e) = self.introduce(server, from) {
+                        warn!(error = %_e, "peers not introduced");
+                    }
+                } else {
+                    debug!(name = %name, "server not registered");
+                }
+            }
+            Ok(_) => {}
+            Err(_e) => debug!(error = %_e, "request dropped"),
+        }
+    }
+
+    fn introduce(&mut self, server: SocketAddr, client: SocketAddr) -> Result<(), Exception> {
+        info!(server = %server, client = %client, "peers introduced");
+        let to_server = bll::envelope::packet(Rendezvous::Introduce(client));
+        self.socket
+            .write(&server, &ClientPacket::<()>::Rendezvous(to_server))?;
+        let to_client = bll::envelope::packet(Rendezvous::Introduce(server));
+        self.socket
+            .write(&client, &ServerPacket::<()>::Rendezvous(to_client))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,184 @@
+use crate::{
+    Capture, ClientSocketBuilder, Clock, Codec, Demo, DemoEvent, Exception, Game,
+    GameServerBuilder, ManualClock, Message, ReplayTransport, SystemClock, Tick,
+};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+fn start(capture: &Capture) -> Duration {
+    capture
+        .datagrams()
+        .first()
+        .map_or_else(|| Duration::new(0, 0), |d| d.time)
+}
+
+///Runs game with commands from capture recorded by server.
+/// Time of server is moved to time datagrams were received.
+/// Returns game after all datagrams of capture were handled or game stopped.
+pub fn replay_server<T: Game, K: Codec>(
+    capture: Capture,
+    game: T,
+    codec: K,
+) -> Result<T, Exception> {
+    let clock = ManualClock::new(start(&capture));
+    let address = SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0);
+    let transport = ReplayTransport::new(address, capture, clock.clone());
+    let mut server = GameServerBuilder::new(0)
+        .codec(codec)
+        .transport(transport.clone())
+        .clock(clock)
+        .build(game)?;
+    while server.is_running && !transport.is_finished() {
+        server.iterate();
+    }
+    Ok(server.game)
+}
+
+///Reads states from capture recorded by client.
+/// Returns results of every read except ones with [`std::io::ErrorKind::WouldBlock`].
+pub fn replay_client<C: Message, S: Message, K: Codec>(
+    capture: Capture,
+    codec: K,
+) -> Result<Vec<Result<S, Exception>>, Exception> {
+    let clock = ManualClock::new(start(&capture));
+    let server_address = match capture.datagrams().first() {
+        Some(d) => d.peer,
+        None => return Ok(Vec::new()),
+    };
+    let address = SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0);
+    let transport = ReplayTransport::new(address, capture, clock.clone());
+    let mut client = ClientSocketBuilder::new(0)
+        .codec(codec)
+        .transport(transport.clone())
+        .clock(clock)
+        .build::<C, S>(server_address)?;
+    let mut states = Vec::new();
+    while !transport.is_finished() {
+        match client.recv() {
+            Err(Exception::IoError(ref e)) if e.kind() == io::ErrorKind::WouldBlock => {}
+            r => states.push(r),
+        }
+    }
+    Ok(states)
+}
+
+///Plays states of [`Demo`] as if they came from [`crate::ClientSocket::recv`].
+pub struct ReplayClient<S> {
+    frames: Vec<(Tick, Duration, S)>,
+    next: usize,
+    position: Duration,
+    instant: Duration,
+    speed: f64,
+    is_paused: bool,
+    tick: Tick,
+    clock: Arc<dyn Clock>,
+}
+
+impl<S: Clone> ReplayClient<S> {
+    ///Creates client playing demo from start with normal speed.
+    pub fn new<C>(demo: Demo<C, S>) -> ReplayClient<S> {
+        let frames: Vec<_> = demo
+            .into_events()
+            .into_iter()
+            .filter_map(|e| match e {
+                DemoEvent::Draw { tick, time, state } => Some((tick, time, state)),
+                DemoEvent::Commands { .. } => None,
+            })
+            .collect();
+        //Time in demo is counted from first drawn state.
+        let start = frames.first().map_or_else(|| Duration::new(0, 0), |f| f.1);
+        let frames = frames
+            .into_iter()
+            .map(|(tick, time, state)| (tick, time - start, state))
+            .collect();
+        let clock: Arc<dyn Clock> = Arc::new(SystemClock::new());
+        ReplayClient {
+            frames,
+            next: 0,
+            position: Duration::new(0, 0),
+            instant: clock.now(),
+            speed: 1.0,
+            is_paused: false,
+            tick: 0,
+            clock,
+        }
+    }
+
+    ///Sets source of time. By default it is [`SystemClock`].
+    pub fn clock(mut self, clock: impl Clock + 'static) -> ReplayClient<S> {
+        self.instant = clock.now();
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    ///Returns next state if it is time to show it.
+    /// Return [`Exception`] with [`std::io::ErrorKind::WouldBlock`] if there is no state to show.
+    pub fn recv(&mut self) -> Result<S, Exception> {
+        self.play();
+        match self.frames.get(self.next) {
+            Some((tick, time, state)) if *time <= self.position => {
+                self.next += 1;
+                self.tick = *tick;
+                Ok(state.clone())
+            }
+            _ => Err(io::Error::from(io::ErrorKind::WouldBlock).into()),
+        }
+    }
+
+    ///Server tick on which last returned state was drawn.
+    pub fn tick(&self) -> Tick {
+        self.tick
+    }
+
+    ///Moves playback to first state drawn on or after tick.
+    pub fn seek(&mut self, tick: Tick) {
+        self.play();
+        self.next = self
+            .frames
+            .iter()
+            .position(|f| f.0 >= tick)
+            .unwrap_or(self.frames.len());
+        if let Some(f) = self.frames.get(self.next) {
+            self.position = f.1;
+        }
+    }
+
+    pub fn pause(&mut self) {
+        self.play();
+        self.is_paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.play();
+        self.is_paused = false;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.is_paused
+    }
+
+    ///Sets speed of playback. 1.0 is speed demo was recorded with.
+    pub fn set_speed(&mut self, speed: f64) {
+        self.play();
+        self.speed = speed.max(0.0);
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    ///True if all states of demo were returned.
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.frames.len()
+    }
+
+    fn play(&mut self) {
+        let now = self.clock.now();
+        if !self.is_paused {
+            self.position += (now - self.instant).mul_f64(self.speed);
+        }
+        self.instant = now;
+    }
+}
@@ -0,0 +1,247 @@
+use crate::data_access_layer::room::{RoomInbox, RoomTransport, Router};
+use crate::peers::Peers;
+use crate::{
+    Bincode, Capacity, ClientId, ClientPacket, Clock, Codec, Game, GameServer, ServerEvent,
+    ServerSocket, ShutdownHandle, ShutdownSummary, Timeouts, Transport, MAX_FLUSHED_PACKETS,
+    STOPPED_BY_GAME,
+};
+use std::collections::HashMap;
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicU32, AtomicUsize};
+use std::sync::Arc;
+
+///Id of room in [`RoomServer`].
+pub type RoomId = u32;
+
+const ROOM_REPLACED: &str = "Room replaced";
+///Reason of [`crate::ServerEvent::ClientDisconnected`] game of room gets when client moved to other room.
+pub const MOVED_TO_ROOM: &str = "Moved to other room";
+
+struct Room<T: Game, K: Codec, N: Transport> {
+    server: GameServer<T, K, RoomTransport<N>>,
+    inbox: RoomInbox,
+}
+
+///Runs many [`Game`] in rooms on one transport.
+/// Every room has its own update - simulate - draw circle and clients.
+/// Create it with [`crate::GameServerBuilder::build_rooms`].
+pub struct RoomServer<T: Game, K: Codec + Clone = Bincode, N: Transport = UdpSocket> {
+    router: Router<N>,
+    rooms: HashMap<RoomId, Room<T, K, N>>,
+    lobby: Option<RoomId>,
+    codec: K,
+    clock: Arc<dyn Clock>,
+    timeouts: Timeouts,
+    capacity: Capacity,
+    //Shared by sockets of rooms so client keeps its id in other room.
+    ids: Arc<AtomicU32>,
+    connected: Arc<AtomicUsize>,
+    shutdown: ShutdownHandle,
+}
+
+impl<T: Game, K: Codec + Clone, N: Transport> RoomServer<T, K, N> {
+    pub(crate) fn new(
+        transport: N,
+        codec: K,
+        clock: Arc<dyn Clock>,
+        timeouts: Timeouts,
+        capacity: Capacity,
+    ) -> RoomServer<T, K, N> {
+        RoomServer {
+            router: Router::new(transport),
+            rooms: HashMap::new(),
+            lobby: None,
+            codec,
+            clock,
+            timeouts,
+            capacity,
+            ids: Arc::new(AtomicU32::new(0)),
+            connected: Arc::new(AtomicUsize::new(0)),
+            shutdown: ShutdownHandle::default(),
+        }
+    }
+
+    ///Creates room running game.
+    /// If room with same id exists it is destroyed and its game returned.
+    pub fn create_room(&mut self, id: RoomId, game: T) -> Option<T> {
+        let old = self.destroy_room(id, ROOM_REPLACED);
+        let transport = self.router.room();
+        let inbox = transport.inbox();
+        let mut socket = ServerSocket::new(transport, self.codec.clone(), self.clock.clone());
+        socket.ids = Arc::clone(&self.ids);
+        socket.connected = Arc::clone(&self.connected);
+        socket.timeouts = self.timeouts;
+        socket.set_capacity(self.capacity);
+        let server = GameServer::with_peers(game, Peers::Local(Box::new(socket)), self.clock.clone());
+        info!(room = id, "room created");
+        self.rooms.insert(id, Room { server, inbox });
+        old
+    }
+
+    ///Destroys room and sends disconnect with reason to its clients.
+    /// Returns game of room.
+    pub fn destroy_room(&mut self, id: RoomId, reason: &str) -> Option<T> {
+        let mut room = self.rooms.remove(&id)?;
+        room.server.shutdown.shutdown(reason);
+        room.server.close();
+        if self.lobby == Some(id) {
+            self.lobby = None;
+        }
+        info!(room = id, reason = %reason, "room destroyed");
+        Some(room.server.game)
+    }
+
+    ///Room new clients are sent to.
+    /// Without it commands from unknown clients are dropped.
+    pub fn set_lobby(&mut self, id: Option<RoomId>) {
+        self.lobby = id;
+    }
+
+    ///Moves client with its session and id to other room. Old room stops sending states to client.
+    /// Game of old room gets [`ServerEvent::ClientDisconnected`] with [`MOVED_TO_ROOM`].
+    /// Returns false if there is no such room or client.
+    pub fn move_client(&mut self, client: ClientId, to: RoomId) -> bool {
+        let from = match self.client_room(client) {
+            Some(from) if self.rooms.contains_key(&to) => from,
+            _ => return false,
+        };
+        if from == to {
+            return true;
+        }
+        let old = &mut self.rooms.get_mut(&from).unwrap().server;
+        let session = match &mut old.socket {
+            Peers::Local(socket) => socket.take(client),
+            Peers::Sharded(_) => None,
+        };
+        let user = old.users.remove(&client);
+        old.handle_server_event(ServerEvent::ClientDisconnected((
+            client,
+            MOVED_TO_ROOM.to_owned(),
+        )));
+        let new = &mut self.rooms.get_mut(&to).unwrap().server;
+        if let (Some(session), Peers::Local(socket)) = (session, &mut new.socket) {
+            socket.put(client, session);
+        }
+        if let Some(user) = user {
+            new.users.insert(client, user);
+        }
+        info!(client, from, to, "peer moved");
+        true
+    }
+
+    ///Room client is in.
+    pub fn client_room(&self, client: ClientId) -> Option<RoomId> {
+        self.rooms
+            .iter()
+            .find(|(_, r)| r.server.socket.contains(client))
+            .map(|(id, _)| *id)
+    }
+
+    //Room of client sending from address.
+    fn address_room(&self, address: &SocketAddr) -> Option<RoomId> {
+        self.rooms
+            .iter()
+            .find(|(_, r)| r.server.socket.client(address).is_some())
+            .map(|(id, _)| *id)
+    }
+
+    pub fn game(&self, id: RoomId) -> Option<&T> {
+        self.rooms.get(&id).map(|r| &r.server.game)
+    }
+
+    pub fn game_mut(&mut self, id: RoomId) -> Option<&mut T> {
+        self.rooms.get_mut(&id).map(|r| &mut r.server.game)
+    }
+
+    ///Ids of all rooms.
+    pub fn rooms(&self) -> Vec<RoomId> {
+        self.rooms.keys().copied().collect()
+    }
+
+    ///Handle to stop server from other thread.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    ///Runs rooms until [`ShutdownHandle`] stops server.
+    /// Blocks current thread.
+    /// Room is destroyed when its game stops.
+    pub fn run(&mut self) -> Vec<(RoomId, ShutdownSummary)> {
+        while !self.shutdown.is_shutdown() {
+            self.run_once();
+        }
+        let reason = self.shutdown.reason().unwrap_or_default();
+        let mut summaries = Vec::new();
+        for (id, mut room) in self.rooms.drain() {
+            room.server.shutdown.shutdown(reason.clone());
+            summaries.push((id, room.server.close()));
+        }
+        summaries
+    }
+
+    ///Runs one circle of every room.
+    /// Use it instead of run to create, destroy rooms and move clients between circles.
+    pub fn run_once(&mut self) {
+        self.route();
+        let mut stopped = Vec::new();
+        for (id, room) in &mut self.rooms {
+            for _ in 0..MAX_FLUSHED_PACKETS {
+                if !room.server.update() {
+                    break;
+                }
+            }
+            room.server.simulate();
+            room.server.draw();
+            if !room.server.is_running {
+                stopped.push(*id);
+            }
+        }
+        for id in stopped {
+            self.destroy_room(id, STOPPED_BY_GAME);
+        }
+    }
+
+    fn route(&mut self) {
+        for _ in 0..MAX_FLUSHED_PACKETS {
+            let (datagram, from) = match self.router.recv() {
+                Ok(r) => r,
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return,
+                Err(_e) => {
+                    warn!(error = %_e, "datagram not routed");
+                    return;
+                }
+            };
+            let id = match self
+                .address_room(&from)
+                .or_else(|| self.session_room(&datagram))
+                .or(self.lobby)
+            {
+                Some(id) => id,
+                None => {
+                    debug!(peer = %from, "datagram from client without room dropped");
+                    continue;
+                }
+            };
+            if let Some(room) = self.rooms.get(&id) {
+                room.inbox.push(datagram, from);
+            }
+        }
+    }
+
+    //Client from new address keeps its room if room knows its session.
+    // Rooms own their clients so nothing is stored here for unknown senders.
+    fn session_room(&self, datagram: &[u8]) -> Option<RoomId> {
+        let command = match self.codec.deserialize(datagram) {
+            Ok(ClientPacket::<T::Command>::Command(command)) => command,
+            _ => return None,
+        };
+        self.rooms
+            .iter()
+            .find(|(_, r)| match &r.server.socket {
+                Peers::Local(socket) => socket.session(&command).is_some(),
+                Peers::Sharded(_) => false,
+            })
+            .map(|(id, _)| *id)
+    }
+}
@@ -0,0 +1,51 @@
+//Macros forwarding to tracing if feature "tracing" enabled and doing nothing otherwise.
+
+macro_rules! trace {
+    ($($arg:tt)*) => {{
+        #[cfg(feature = "tracing")]
+        {
+            tracing::trace!($($arg)*);
+        }
+    }};
+}
+
+macro_rules! debug {
+    ($($arg:tt)*) => {{
+        #[cfg(feature = "tracing")]
+        {
+            tracing::debug!($($arg)*);
+        }
+    }};
+}
+
+macro_rules! info {
+    ($($arg:tt)*) => {{
+        #[cfg(feature = "tracing")]
+        {
+            tracing::info!($($arg)*);
+        }
+    }};
+}
+
+macro_rules! warn {
+    ($($arg:tt)*) => {{
+        #[cfg(feature = "tracing")]
+        {
+            tracing::warn!($($arg)*);
+        }
+    }};
+}
+
+///Enters span of peer until returned guard dropped.
+macro_rules! peer_span {
+    ($peer:expr) => {{
+        #[cfg(feature = "tracing")]
+        let span = tracing::debug_span!("peer", peer = %$peer).entered();
+        #[cfg(not(feature = "tracing"))]
+        let span = $crate::trace::NoSpan;
+        span
+    }};
+}
+
+#[cfg(not(feature = "tracing"))]
+pub struct NoSpan;
@@ -3,18 +3,27 @@ use std::error::Error;
 use std::net::SocketAddr;
 use std::ops::{Add, Mul};
 use std::time::Duration;
-use victorem;
-use victorem::{ClientSocket, ContinueRunning, Exception, Game, GameServer, ServerEvent};
+use victorem::{
+    ClientId, ClientSocket, ClientView, ContinueRunning, Exception, Game, GameServer, ServerEvent,
+    ServerInfo, ServerStatus, Tick, UserData,
+};
 
 struct GameData {
     events: Vec<ServerEvent>,
-    updates: Vec<(Duration, Vec<Vec<u8>>, SocketAddr)>,
+    updates: Vec<(Duration, Vec<Vec<u8>>, ClientId)>,
     continue_on_command: bool,
-    disconnect_this_client: Option<SocketAddr>,
+    disconnect_this_client: Option<ClientId>,
     draw: Vec<u8>,
     drawn: Vec<Duration>,
     new_client: Option<SocketAddr>,
     continue_on_event: bool,
+    ticks: Vec<Tick>,
+    info: Option<ServerInfo>,
+    status: Option<ServerStatus>,
+    password: Option<Vec<u8>>,
+    users: Vec<(ClientId, Option<String>)>,
+    views: Vec<(Duration, Option<Duration>)>,
+    banned: Option<SocketAddr>,
 }
 
 impl GameData {
@@ -31,6 +40,22 @@ impl GameData {
                 7777,
             )),
             continue_on_event: true,
+            ticks: Vec::new(),
+            info: None,
+            status: None,
+            password: None,
+            users: Vec::new(),
+            views: Vec::new(),
+            banned: None,
+        }
+    }
+
+    //Game drawing same state only to clients connected by themselves.
+    fn with_draw(draw: Vec<u8>) -> GameData {
+        GameData {
+            new_client: None,
+            draw,
+            ..GameData::new()
         }
     }
 }
@@ -42,7 +67,7 @@ struct GameMock<'a> {
 }
 
 impl<'a> GameMock<'a> {
-    fn new(data: &'a mut GameData, counter: usize) -> GameMock {
+    fn new(data: &'a mut GameData, counter: usize) -> GameMock<'a> {
         GameMock {
             data,
             counter,
@@ -52,34 +77,75 @@ impl<'a> GameMock<'a> {
 }
 
 impl<'a> Game for GameMock<'a> {
+    type Command = Vec<u8>;
+    type State = Vec<u8>;
+
+    fn update(&mut self, tick: Tick, _fixed_dt: Duration) -> ContinueRunning {
+        self.data.ticks.push(tick);
+        true
+    }
+
     fn handle_command(
         &mut self,
+        _tick: Tick,
         delta_time: Duration,
         commands: Vec<Vec<u8>>,
-        from: SocketAddr,
+        from: ClientId,
+        view: ClientView<Vec<u8>>,
     ) -> bool {
         self.data.updates.push((delta_time, commands, from));
+        self.data.users.push((from, view.user().map(|u| u.name.clone())));
+        self.data
+            .views
+            .push((view.time(), view.snapshot().map(|s| s.time)));
         self.data.continue_on_command
     }
 
-    fn draw(&mut self, delta_time: Duration) -> Vec<u8> {
+    fn draw(&mut self, _tick: Tick, delta_time: Duration) -> Option<Vec<u8>> {
         self.data.drawn.push(delta_time);
         self.current += 1;
         if self.current > self.counter {
             self.data.continue_on_event = false;
         }
-        self.data.draw.clone()
+        if self.data.draw.is_empty() {
+            None
+        } else {
+            Some(self.data.draw.clone())
+        }
     }
 
-    fn handle_server_event(&mut self, event: ServerEvent) -> ContinueRunning {
+    fn handle_server_event(&mut self, _tick: Tick, event: ServerEvent) -> ContinueRunning {
         self.data.events.push(event);
-        self.data.continue_on_event.clone()
+        self.data.continue_on_event
+    }
+    fn add_client(&mut self, _tick: Tick) -> Option<SocketAddr> {
+        self.data.new_client
     }
-    fn add_client(&mut self) -> Option<SocketAddr> {
-        self.data.new_client.clone()
+    fn allow_connect(&mut self, _tick: Tick, _client: ClientId, from: &SocketAddr) -> bool {
+        self.data.banned != Some(*from)
     }
-    fn remove_client(&mut self) -> Option<SocketAddr> {
-        self.data.disconnect_this_client.clone()
+    fn remove_client(&mut self, _tick: Tick) -> Option<ClientId> {
+        self.data.disconnect_this_client
+    }
+    fn server_info(&mut self, _tick: Tick) -> Option<ServerInfo> {
+        self.data.info.clone()
+    }
+    fn server_status(&mut self, _tick: Tick) -> Option<ServerStatus> {
+        self.data.status.clone()
+    }
+    fn authenticate(
+        &mut self,
+        _tick: Tick,
+        client: ClientId,
+        payload: &[u8],
+    ) -> Result<UserData, String> {
+        match &self.data.password {
+            Some(password) if password.as_slice() != payload => Err("Bad password".to_owned()),
+            _ => Ok(UserData {
+                name: format!("player {}", client),
+                ..Default::default()
+            }),
+        }
     }
 }
 
@@ -97,10 +163,10 @@ fn server_should_send_state_to_client_on_draw() -> Result<(), Exception> {
             game_server.run();
         }
     });
-    let mut client = ClientSocket::new(4444, "127.0.0.1:3336")?;
-    client.send(vec![1u8]);
-    client.send(vec![1u8]);
-    client.send(vec![1u8]);
+    let mut client = ClientSocket::<Vec<u8>, Vec<u8>>::new(4444, "127.0.0.1:3336")?;
+    client.send(vec![1u8])?;
+    client.send(vec![1u8])?;
+    client.send(vec![1u8])?;
     let res = loop {
         match client.recv() {
             Ok(r) => break r,
@@ -114,15 +180,14 @@ fn server_should_send_state_to_client_on_draw() -> Result<(), Exception> {
 #[test]
 fn server_should_stop_if_handle_command_returns_false() -> Result<(), Exception> {
     std::thread::spawn(|| {
-        let res = ClientSocket::new(1112, "127.0.0.1:3333")
+        ClientSocket::<Vec<u8>, Vec<u8>>::new(1112, "127.0.0.1:3333")
             .map(|mut c| {
                 for _i in 0..1000 {
                     let _ = c.send(vec![1u8, 3u8]);
                 }
                 1
             })
-            .unwrap_or(0);
-        res
+            .unwrap_or(0)
     });
     let timer = std::time::Instant::now();
     let start = timer.elapsed();
@@ -153,15 +218,14 @@ fn server_should_stop_if_handle_event_returns_false() -> Result<(), Exception> {
 #[test]
 fn server_should_recv_commands_from_client() -> Result<(), Exception> {
     std::thread::spawn(|| {
-        let res = ClientSocket::new(1111, "127.0.0.1:3335")
+        ClientSocket::<Vec<u8>, Vec<u8>>::new(1111, "127.0.0.1:3335")
             .map(|mut c| {
                 for _i in 0..1000 {
                     let _ = c.send(vec![1u8, 3u8]);
                 }
                 1
             })
-            .unwrap_or(0);
-        res
+            .unwrap_or(0)
     });
 
     let mut game_data = GameData::new();
@@ -180,10 +244,1724 @@ fn server_should_recv_commands_from_client() -> Result<(), Exception> {
     Ok(())
 }
 
+#[test]
+fn server_should_update_game_with_consecutive_ticks() -> Result<(), Exception> {
+    let mut game_data = GameData::new();
+    let game_mock = GameMock::new(&mut game_data, 5);
+    let mut game_server = create_server(game_mock, 3337)?;
+    game_server.run();
+    assert!(!game_data.ticks.is_empty());
+    let expected: Vec<Tick> = (1..=game_data.ticks.len() as Tick).collect();
+    assert_eq!(expected, game_data.ticks);
+    Ok(())
+}
+
+#[test]
+fn server_should_report_not_deserialized_command_with_sender() -> Result<(), Exception> {
+    std::thread::spawn(|| {
+        let socket = std::net::UdpSocket::bind("127.0.0.1:1113")?;
+        for _i in 0..100 {
+            socket.send_to(&[255u8; 3], "127.0.0.1:3338")?;
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        Ok::<(), std::io::Error>(())
+    });
+    let mut game_data = GameData::new();
+    let game_mock = GameMock::new(&mut game_data, 10);
+    let mut game_server = create_server(game_mock, 3338)?;
+    game_server.run();
+    assert!(game_data.events.iter().any(|e| match e {
+        ServerEvent::ExceptionOnRecvFrom((from, Exception::BincodeError(_))) => from.port() == 1113,
+        _ => false,
+    }));
+    Ok(())
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn client_and_server_should_speak_json() -> Result<(), Exception> {
+    std::thread::spawn(|| {
+        let mut game_data = GameData::new();
+        game_data.draw = vec![4u8, 2];
+        let game_mock = GameMock::new(&mut game_data, 100000);
+        if let Ok(mut game_server) = victorem::GameServerBuilder::new(3339)
+            .codec(victorem::Json)
+            .build(game_mock)
+        {
+            game_server.run();
+        }
+    });
+    let mut client = victorem::ClientSocketBuilder::new(4445)
+        .codec(victorem::Json)
+        .build::<Vec<u8>, Vec<u8>>("127.0.0.1:3339")?;
+    client.send(vec![1u8])?;
+    let timer = std::time::Instant::now();
+    let res = loop {
+        match client.recv() {
+            Ok(r) => break r,
+            Err(_) => assert!(timer.elapsed() < Duration::from_secs(5)),
+        }
+    };
+    assert_eq!(vec![4u8, 2], res);
+    Ok(())
+}
+
+#[test]
+fn server_should_send_state_to_many_clients_over_memory_network() -> Result<(), Exception> {
+    let network = victorem::MemoryNetwork::new();
+    let server_transport = network.bind("10.0.0.1:1000")?;
+    std::thread::spawn(move || {
+        let mut game_data = GameData::new();
+        game_data.draw = vec![5u8, 6];
+        let game_mock = GameMock::new(&mut game_data, 100000);
+        if let Ok(mut game_server) = victorem::GameServerBuilder::new(0)
+            .transport(server_transport)
+            .build(game_mock)
+        {
+            game_server.run();
+        }
+    });
+    let mut clients = Vec::new();
+    for i in 0..3u8 {
+        let mut client = victorem::ClientSocketBuilder::new(0)
+            .transport(network.bind(("10.0.0.2", 2000 + u16::from(i)))?)
+            .build::<Vec<u8>, Vec<u8>>("10.0.0.1:1000")?;
+        client.send(vec![i])?;
+        clients.push(client);
+    }
+    let timer = std::time::Instant::now();
+    for client in &mut clients {
+        let res = loop {
+            match client.recv() {
+                Ok(r) => break r,
+                Err(_) => assert!(timer.elapsed() < Duration::from_secs(5)),
+            }
+        };
+        assert_eq!(vec![5u8, 6], res);
+    }
+    Ok(())
+}
+
+#[test]
+fn server_should_recv_ordered_commands_over_bad_network() -> Result<(), Exception> {
+    let network = victorem::MemoryNetwork::new();
+    let server_transport = network.bind("10.0.1.1:1000")?;
+    let client_transport = victorem::LinkConditioner::new(
+        network.bind("10.0.1.2:2000")?,
+        victorem::LinkConditions {
+            latency: Duration::from_millis(5),
+            jitter: Duration::from_millis(20),
+            duplicate_percent: 30.0,
+            reorder_percent: 30.0,
+            ..victorem::LinkConditions::default()
+        },
+        42,
+    );
+    std::thread::spawn(move || {
+        let mut client = victorem::ClientSocketBuilder::new(0)
+            .transport(client_transport)
+            .build::<Vec<u8>, Vec<u8>>("10.0.1.1:1000")?;
+        for i in 1..=10u8 {
+            client.send(vec![i])?;
+        }
+        let timer = std::time::Instant::now();
+        while timer.elapsed() < Duration::from_millis(200) {
+            let _ = client.recv();
+        }
+        Ok::<(), Exception>(())
+    });
+    let mut game_data = GameData::new();
+    let game_mock = GameMock::new(&mut game_data, 30);
+    let mut game_server = victorem::GameServerBuilder::new(0)
+        .transport(server_transport)
+        .build(game_mock)?;
+    game_server.run();
+    let commands: Vec<Vec<u8>> = game_data
+        .updates
+        .iter()
+        .flat_map(|(_, c, _)| c.clone())
+        .collect();
+    let expected: Vec<Vec<u8>> = (1..=commands.len() as u8).map(|i| vec![i]).collect();
+    assert!(commands.len() >= 9, "commands {:?}", commands);
+    assert_eq!(expected, commands);
+    Ok(())
+}
+
+#[test]
+fn link_conditioner_should_send_held_datagram_when_traffic_stops() -> Result<(), Exception> {
+    use victorem::Transport;
+    fn sync<T: Sync>(_: &T) {}
+    let network = victorem::MemoryNetwork::new();
+    let receiver = network.bind("10.0.19.1:1000")?;
+    let clock = victorem::ManualClock::default();
+    let conditioner = victorem::LinkConditioner::new(
+        network.bind("10.0.19.2:2000")?,
+        victorem::LinkConditions {
+            latency: Duration::from_millis(5),
+            reorder_percent: 100.0,
+            ..victorem::LinkConditions::default()
+        },
+        42,
+    )
+    .clock(clock.clone());
+    sync(&conditioner);
+    conditioner.send_to(&[1], &"10.0.19.1:1000".parse().unwrap())?;
+    let mut buf = [0u8; 8];
+    assert!(receiver.recv_from(&mut buf).is_err());
+    clock.advance(Duration::from_millis(5));
+    assert!(conditioner.recv_from(&mut buf).is_err());
+    let (len, _) = receiver.recv_from(&mut buf)?;
+    assert_eq!(&[1], &buf[..len]);
+    Ok(())
+}
+
+struct FullDisk;
+
+impl std::io::Write for FullDisk {
+    fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+        Err(std::io::Error::from(std::io::ErrorKind::Other))
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn failed_capture_and_demo_should_not_break_game() -> Result<(), Exception> {
+    let network = victorem::MemoryNetwork::new();
+    let server_transport = network.bind("10.0.20.1:1000")?;
+    let mut client = victorem::ClientSocketBuilder::new(0)
+        .transport(network.bind("10.0.20.2:2000")?)
+        .record(FullDisk)
+        .build::<Vec<u8>, Vec<u8>>("10.0.20.1:1000")?;
+    client.send(vec![1])?;
+    assert!(client.capture_error().is_some());
+    let mut game_data = GameData::with_draw(vec![7]);
+    let game_mock = GameMock::new(&mut game_data, 100000);
+    let mut game_server = victorem::GameServerBuilder::new(0)
+        .transport(server_transport)
+        .record(FullDisk)
+        .record_demo(FullDisk)
+        .build(game_mock)?;
+    let timer = std::time::Instant::now();
+    let state = loop {
+        game_server.run_once();
+        if let Ok(state) = client.recv() {
+            break state;
+        }
+        assert!(timer.elapsed() < Duration::from_secs(5));
+    };
+    assert_eq!(vec![7], state);
+    game_server.close();
+    assert_eq!(1, game_data.updates.len());
+    let captures = game_data
+        .events
+        .iter()
+        .filter(|e| matches!(e, ServerEvent::ExceptionOnCapture(_)))
+        .count();
+    assert_eq!(1, captures);
+    let demos = game_data
+        .events
+        .iter()
+        .filter(|e| matches!(e, ServerEvent::ExceptionOnRecord(_)))
+        .count();
+    assert_eq!(1, demos);
+    Ok(())
+}
+
+#[test]
+fn server_and_client_captures_should_replay_offline() -> Result<(), Exception> {
+    let server_path = std::env::temp_dir().join("victorem_server_replay_test.cap");
+    let client_path = std::env::temp_dir().join("victorem_client_replay_test.cap");
+    let network = victorem::MemoryNetwork::new();
+    let server_transport = network.bind("10.0.2.1:1000")?;
+    let client_transport = network.bind("10.0.2.2:2000")?;
+    let client_capture = std::fs::File::create(&client_path)?;
+    let client = std::thread::spawn(move || {
+        let mut client = victorem::ClientSocketBuilder::new(0)
+            .transport(client_transport)
+            .record(client_capture)
+            .build::<Vec<u8>, Vec<u8>>("10.0.2.1:1000")?;
+        for i in 1..=3u8 {
+            client.send(vec![i])?;
+        }
+        let timer = std::time::Instant::now();
+        while timer.elapsed() < Duration::from_millis(200) {
+            let _ = client.recv();
+        }
+        Ok::<(), Exception>(())
+    });
+    let mut game_data = GameData::with_draw(vec![7]);
+    let game_mock = GameMock::new(&mut game_data, 30);
+    let mut game_server = victorem::GameServerBuilder::new(0)
+        .transport(server_transport)
+        .record(std::fs::File::create(&server_path)?)
+        .build(game_mock)?;
+    game_server.run();
+    drop(game_server);
+    client.join().unwrap()?;
+
+    let mut replayed_data = GameData::with_draw(vec![7]);
+    let capture = victorem::Capture::read(std::fs::File::open(&server_path)?)?;
+    victorem::replay_server(
+        capture,
+        GameMock::new(&mut replayed_data, 100000),
+        victorem::Bincode,
+    )?;
+    let commands = |data: &GameData| -> Vec<(Vec<Vec<u8>>, ClientId)> {
+        data.updates.iter().map(|(_, c, a)| (c.clone(), *a)).collect()
+    };
+    assert_eq!(3, commands(&game_data).len());
+    assert_eq!(commands(&game_data), commands(&replayed_data));
+
+    let capture = victorem::Capture::read(std::fs::File::open(&client_path)?)?;
+    let received = capture
+        .datagrams()
+        .iter()
+        .filter(|d| d.direction == victorem::Direction::Received)
+        .count();
+    let states = victorem::replay_client::<Vec<u8>, Vec<u8>, _>(capture, victorem::Bincode)?;
+    assert!(received > 0);
+    assert_eq!(received, states.len());
+    assert!(states.into_iter().all(|s| s.ok() == Some(vec![7])));
+    Ok(())
+}
+
+#[test]
+fn replay_client_should_play_recorded_match() -> Result<(), Exception> {
+    let path = std::env::temp_dir().join("victorem_demo_test.dem");
+    let network = victorem::MemoryNetwork::new();
+    let server_transport = network.bind("10.0.3.1:1000")?;
+    let mut client = victorem::ClientSocketBuilder::new(0)
+        .transport(network.bind("10.0.3.2:2000")?)
+        .build::<Vec<u8>, Vec<u8>>("10.0.3.1:1000")?;
+    for i in 1..=3u8 {
+        client.send(vec![i])?;
+    }
+    let mut game_data = GameData::new();
+    game_data.draw = vec![7];
+    let game_mock = GameMock::new(&mut game_data, 10);
+    let mut game_server = victorem::GameServerBuilder::new(0)
+        .transport(server_transport)
+        .record_demo(std::fs::File::create(&path)?)
+        .build(game_mock)?;
+    game_server.run();
+    drop(game_server);
+
+    let demo = victorem::Demo::<Vec<u8>, Vec<u8>>::read(std::fs::File::open(&path)?)?;
+    let commands: Vec<Vec<u8>> = demo
+        .events()
+        .iter()
+        .flat_map(|e| match e {
+            victorem::DemoEvent::Commands { commands, .. } => commands.clone(),
+            victorem::DemoEvent::Draw { .. } => Vec::new(),
+        })
+        .collect();
+    assert_eq!(vec![vec![1u8], vec![2], vec![3]], commands);
+    let ticks: Vec<Tick> = demo
+        .events()
+        .iter()
+        .filter_map(|e| match e {
+            victorem::DemoEvent::Draw { tick, .. } => Some(*tick),
+            victorem::DemoEvent::Commands { .. } => None,
+        })
+        .collect();
+    assert!(ticks.len() > 2);
+
+    let clock = victorem::ManualClock::default();
+    let mut replay = victorem::ReplayClient::new(demo).clock(clock.clone());
+    assert_eq!(Ok(vec![7u8]), replay.recv().map_err(|e| e.to_string()));
+    assert_eq!(ticks[0], replay.tick());
+    assert!(replay.recv().is_err());
+    replay.pause();
+    clock.advance(Duration::from_secs(10));
+    assert!(replay.recv().is_err());
+    replay.resume();
+    replay.set_speed(2.0);
+    clock.advance(Duration::from_secs(5));
+    let mut played = 1;
+    while replay.recv().is_ok() {
+        played += 1;
+    }
+    assert_eq!(ticks.len(), played);
+    assert!(replay.is_finished());
+    replay.seek(ticks[1]);
+    assert!(replay.recv().is_ok());
+    assert_eq!(ticks[1], replay.tick());
+    Ok(())
+}
+
+#[test]
+fn server_should_serve_metrics_in_prometheus_format() -> Result<(), Exception> {
+    use std::io::{Read, Write};
+    use victorem::Transport;
+    let network = victorem::MemoryNetwork::new();
+    let server_transport = network.bind("10.0.4.1:1000")?;
+    let client_transport = network.bind("10.0.4.2:2000")?;
+    let bad_transport = network.bind("10.0.4.3:3000")?;
+    let metrics_address: SocketAddr = "127.0.0.1:3340".parse().unwrap();
+    let scraper = std::thread::spawn(move || {
+        let mut client = victorem::ClientSocketBuilder::new(0)
+            .transport(client_transport)
+            .build::<Vec<u8>, Vec<u8>>("10.0.4.1:1000")?;
+        client.send(vec![1])?;
+        client.send(vec![2])?;
+        bad_transport.send_to(&[1, 2, 3], &"10.0.4.1:1000".parse().unwrap())?;
+        std::thread::sleep(Duration::from_millis(100));
+        let mut stream = std::net::TcpStream::connect(metrics_address)?;
+        stream.write_all(b"GET /metrics HTTP/1.1\r\n\r\n")?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response)?;
+        //Keeps client connected until server stops.
+        Ok::<_, Exception>((response, client))
+    });
+    let mut game_data = GameData::with_draw(vec![7]);
+    let game_mock = GameMock::new(&mut game_data, 30);
+    let mut game_server = victorem::GameServerBuilder::new(0)
+        .transport(server_transport)
+        .serve_metrics(metrics_address)
+        .build(game_mock)?;
+    game_server.run();
+    let metrics = game_server.metrics();
+    let (response, _client) = scraper.join().unwrap()?;
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+    assert!(response.contains("victorem_peers 1\n"), "{}", response);
+    assert!(response.contains("victorem_receive_errors_total{kind=\"BincodeError\"} 1\n"));
+    assert!(response.contains("victorem_out_of_order_total 0\n"), "{}", response);
+    assert_eq!(1, metrics.peers);
+    assert_eq!(3, metrics.packets_in);
+    assert!(metrics.packets_out > 0);
+    assert!(metrics.bytes_out > 0);
+    assert_eq!(Some(&1), metrics.receive_errors.get("BincodeError"));
+    Ok(())
+}
+
+#[test]
+fn silent_metrics_scraper_should_not_block_server() -> Result<(), Exception> {
+    use std::io::{Read, Write};
+    let network = victorem::MemoryNetwork::new();
+    let clock = victorem::ManualClock::default();
+    let metrics_address: SocketAddr = "127.0.0.1:3341".parse().unwrap();
+    let mut game_data = GameData::new();
+    let game_mock = GameMock::new(&mut game_data, 100000);
+    let mut game_server = victorem::GameServerBuilder::new(0)
+        .transport(network.bind("10.0.21.1:1000")?)
+        .clock(clock.clone())
+        .serve_metrics(metrics_address)
+        .build(game_mock)?;
+    let mut silent = std::net::TcpStream::connect(metrics_address)?;
+    silent.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut scraper = std::net::TcpStream::connect(metrics_address)?;
+    scraper.set_read_timeout(Some(Duration::from_secs(5)))?;
+    scraper.write_all(b"GET /metrics HTTP/1.1\r\n\r\n")?;
+    let mut response = Vec::new();
+    let mut buf = [0u8; 1024];
+    let timer = std::time::Instant::now();
+    while !response.ends_with(b"\n") {
+        game_server.run_once();
+        clock.advance(Duration::from_millis(10));
+        if let Ok(size) = scraper.read(&mut buf) {
+            response.extend_from_slice(&buf[..size]);
+        }
+        assert!(timer.elapsed() < Duration::from_secs(5));
+    }
+    assert!(response.starts_with(b"HTTP/1.1 200 OK"));
+    clock.advance(Duration::from_millis(1_000));
+    game_server.run_once();
+    assert_eq!(0, silent.read(&mut buf)?);
+    game_server.close();
+    Ok(())
+}
+
+#[test]
+fn server_should_notify_clients_on_shutdown() -> Result<(), Exception> {
+    let network = victorem::MemoryNetwork::new();
+    let server_transport = network.bind("10.0.5.1:1000")?;
+    let mut client = victorem::ClientSocketBuilder::new(0)
+        .transport(network.bind("10.0.5.2:2000")?)
+        .build::<Vec<u8>, Vec<u8>>("10.0.5.1:1000")?;
+    client.send(vec![1])?;
+    let mut game_data = GameData::with_draw(vec![7]);
+    let game_mock = GameMock::new(&mut game_data, 100000);
+    let mut game_server = victorem::GameServerBuilder::new(0)
+        .transport(server_transport)
+        .build(game_mock)?;
+    let handle = game_server.shutdown_handle();
+    std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(200));
+        handle.shutdown("maintenance");
+    });
+    let summary = game_server.run();
+    assert_eq!("maintenance", summary.reason);
+    assert_eq!(1, summary.peers);
+    assert!(summary.tick > 0);
+    let reason = loop {
+        match client.recv() {
+            Err(Exception::Disconnected(reason)) => break reason,
+            Ok(state) => assert_eq!(vec![7u8], state),
+            Err(e) => panic!("{}", e),
+        }
+    };
+    assert_eq!("maintenance", reason);
+    Ok(())
+}
+
+//Loses second datagram sent.
+struct LoseSecond<N> {
+    transport: N,
+    sent: std::cell::Cell<u32>,
+}
+
+impl<N: victorem::Transport> victorem::Transport for LoseSecond<N> {
+    fn send_to(&self, buf: &[u8], addr: &SocketAddr) -> std::io::Result<usize> {
+        self.sent.set(self.sent.get() + 1);
+        if self.sent.get() == 2 {
+            return Ok(buf.len());
+        }
+        self.transport.send_to(buf, addr)
+    }
+    fn recv_from(&self, buf: &mut [u8]) -> std::io::Result<(usize, SocketAddr)> {
+        self.transport.recv_from(buf)
+    }
+    fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.transport.local_addr()
+    }
+}
+
+#[test]
+fn server_should_wait_for_lost_commands_on_close() -> Result<(), Exception> {
+    let network = victorem::MemoryNetwork::new();
+    let server_transport = network.bind("10.0.22.1:1000")?;
+    let client_transport = LoseSecond {
+        transport: network.bind("10.0.22.2:2000")?,
+        sent: std::cell::Cell::new(0),
+    };
+    let (closing, is_closing) = std::sync::mpsc::channel();
+    let client = std::thread::spawn(move || {
+        let mut client = victorem::ClientSocketBuilder::new(0)
+            .transport(client_transport)
+            .build::<Vec<u8>, Vec<u8>>("10.0.22.1:1000")?;
+        for i in 1..=3u8 {
+            client.send(vec![i])?;
+        }
+        //Client sees acknowledgement and resends lost command only while server closes.
+        is_closing.recv().unwrap();
+        let timer = std::time::Instant::now();
+        while timer.elapsed() < Duration::from_secs(5) {
+            if let Err(Exception::Disconnected(_)) = client.recv() {
+                break;
+            }
+        }
+        Ok::<(), Exception>(())
+    });
+    let mut game_data = GameData::with_draw(vec![7]);
+    let game_mock = GameMock::new(&mut game_data, 100000);
+    let mut game_server = victorem::GameServerBuilder::new(0)
+        .transport(server_transport)
+        .build(game_mock)?;
+    let timer = std::time::Instant::now();
+    while game_server.metrics().packets_in < 2 {
+        assert!(timer.elapsed() < Duration::from_secs(5), "commands not received");
+        game_server.run_once();
+    }
+    game_server.run_once();
+    closing.send(()).unwrap();
+    game_server.close();
+    client.join().unwrap()?;
+    let commands: Vec<Vec<u8>> = game_data
+        .updates
+        .iter()
+        .flat_map(|(_, c, _)| c.clone())
+        .collect();
+    assert_eq!(vec![vec![1u8], vec![2], vec![3]], commands);
+    Ok(())
+}
+
+#[test]
+fn server_should_remove_disconnected_clients() -> Result<(), Exception> {
+    let network = victorem::MemoryNetwork::new();
+    let server_transport = network.bind("10.0.6.1:1000")?;
+    let first_transport = network.bind("10.0.6.2:2000")?;
+    let second_transport = network.bind("10.0.6.3:2000")?;
+    std::thread::spawn(move || {
+        let mut first = victorem::ClientSocketBuilder::new(0)
+            .transport(first_transport)
+            .build::<Vec<u8>, Vec<u8>>("10.0.6.1:1000")?;
+        let mut second = victorem::ClientSocketBuilder::new(0)
+            .transport(second_transport)
+            .build::<Vec<u8>, Vec<u8>>("10.0.6.1:1000")?;
+        first.send(vec![1])?;
+        second.send(vec![2])?;
+        std::thread::sleep(Duration::from_millis(100));
+        while first.recv().is_ok() || second.recv().is_ok() {}
+        first.disconnect("bye")?;
+        drop(second);
+        Ok::<(), Exception>(())
+    });
+    let mut game_data = GameData::with_draw(vec![7]);
+    let game_mock = GameMock::new(&mut game_data, 20);
+    let mut game_server = victorem::GameServerBuilder::new(0)
+        .transport(server_transport)
+        .build(game_mock)?;
+    let summary = game_server.run();
+    assert_eq!(0, summary.peers);
+    let disconnected: Vec<(ClientId, String)> = game_data
+        .events
+        .iter()
+        .filter_map(|e| match e {
+            ServerEvent::ClientDisconnected(d) => Some(d.clone()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        vec![
+            (1, "bye".to_owned()),
+            (2, "Client dropped".to_owned()),
+        ],
+        disconnected
+    );
+    Ok(())
+}
+
+#[test]
+fn room_server_should_route_clients_to_their_rooms() -> Result<(), Exception> {
+    let network = victorem::MemoryNetwork::new();
+    let server_transport = network.bind("10.0.7.1:1000")?;
+    let mut first = victorem::ClientSocketBuilder::new(0)
+        .transport(network.bind("10.0.7.2:2000")?)
+        .build::<Vec<u8>, Vec<u8>>("10.0.7.1:1000")?;
+    let mut second = victorem::ClientSocketBuilder::new(0)
+        .transport(network.bind("10.0.7.3:2000")?)
+        .build::<Vec<u8>, Vec<u8>>("10.0.7.1:1000")?;
+    let mut lobby_data = GameData::with_draw(vec![1]);
+    let mut match_data = GameData::with_draw(vec![2]);
+    let mut server = victorem::GameServerBuilder::new(0)
+        .transport(server_transport)
+        .build_rooms::<GameMock>()?;
+    server.create_room(1, GameMock::new(&mut lobby_data, 100000));
+    server.create_room(2, GameMock::new(&mut match_data, 100000));
+    server.set_lobby(Some(1));
+    first.send(vec![1])?;
+    second.send(vec![2])?;
+    let timer = std::time::Instant::now();
+    let second_id = loop {
+        server.run_once();
+        let lobby = &server.game(1).unwrap().data.updates;
+        if let Some((_, _, id)) = lobby.iter().find(|(_, c, _)| *c == vec![vec![2u8]]) {
+            break *id;
+        }
+        assert!(timer.elapsed() < Duration::from_secs(5));
+    };
+    assert!(server.move_client(second_id, 2));
+    assert!(!server.move_client(second_id, 3));
+    assert!(!server.move_client(42, 2));
+    assert_eq!(Some(2), server.client_room(second_id));
+    let mut first_state = None;
+    let mut second_state = None;
+    while first_state.is_none() || second_state != Some(vec![2u8]) {
+        server.run_once();
+        if let Ok(s) = first.recv() {
+            first_state = Some(s);
+        }
+        if let Ok(s) = second.recv() {
+            second_state = Some(s);
+        }
+        assert!(timer.elapsed() < Duration::from_secs(5));
+    }
+    assert_eq!(Some(vec![1u8]), first_state);
+    second.send(vec![3])?;
+    while server.game(2).unwrap().data.updates.is_empty() {
+        server.run_once();
+        assert!(timer.elapsed() < Duration::from_secs(5));
+    }
+    server.destroy_room(2, "match over");
+    assert_eq!(None, server.client_room(second_id));
+    let reason = loop {
+        match second.recv() {
+            Err(Exception::Disconnected(reason)) => break reason,
+            _ => assert!(timer.elapsed() < Duration::from_secs(5)),
+        }
+    };
+    assert_eq!("match over", reason);
+    assert_eq!(vec![1], server.rooms());
+    drop(server);
+    let commands: Vec<Vec<Vec<u8>>> = lobby_data.updates.iter().map(|u| u.1.clone()).collect();
+    assert_eq!(vec![vec![vec![1u8]], vec![vec![2u8]]], commands);
+    let first_id = lobby_data.updates[0].2;
+    assert_ne!(first_id, second_id);
+    assert!(lobby_data.events.iter().any(|e| matches!(
+        e,
+        ServerEvent::ClientDisconnected((id, r)) if *id == second_id && r == victorem::MOVED_TO_ROOM
+    )));
+    let moved: Vec<(Vec<Vec<u8>>, ClientId)> = match_data
+        .updates
+        .iter()
+        .map(|u| (u.1.clone(), u.2))
+        .collect();
+    assert_eq!(vec![(vec![vec![3u8]], second_id)], moved);
+    Ok(())
+}
+
+#[test]
+fn room_server_should_keep_migrated_client_in_its_room() -> Result<(), Exception> {
+    let network = victorem::MemoryNetwork::new();
+    let server_transport = network.bind("10.0.23.1:1000")?;
+    let transport = std::sync::Arc::new(std::sync::Mutex::new(network.bind("10.0.23.2:2000")?));
+    let mut client = victorem::ClientSocketBuilder::new(0)
+        .transport(Rebinding(transport.clone()))
+        .build::<Vec<u8>, Vec<u8>>("10.0.23.1:1000")?;
+    let mut lobby_data = GameData::with_draw(vec![1]);
+    let mut match_data = GameData::with_draw(vec![2]);
+    let mut server = victorem::GameServerBuilder::new(0)
+        .transport(server_transport)
+        .build_rooms::<GameMock>()?;
+    server.create_room(1, GameMock::new(&mut lobby_data, 100000));
+    server.create_room(2, GameMock::new(&mut match_data, 100000));
+    server.set_lobby(Some(1));
+    client.send(vec![1])?;
+    let new_address: SocketAddr = "10.0.23.3:3000".parse().unwrap();
+    let timer = std::time::Instant::now();
+    let id = loop {
+        server.run_once();
+        if let Some((_, _, id)) = server.game(1).unwrap().data.updates.first() {
+            break *id;
+        }
+        assert!(timer.elapsed() < Duration::from_secs(5));
+    };
+    assert!(server.move_client(id, 2));
+    while client.recv().ok() != Some(vec![2u8]) {
+        server.run_once();
+        assert!(timer.elapsed() < Duration::from_secs(5));
+    }
+    *transport.lock().unwrap() = network.bind(new_address)?;
+    client.send(vec![3])?;
+    while server.game(2).unwrap().data.updates.is_empty() {
+        server.run_once();
+        assert!(timer.elapsed() < Duration::from_secs(5));
+    }
+    assert_eq!(Some(2), server.client_room(id));
+    drop(server);
+    let lobby: Vec<Vec<Vec<u8>>> = lobby_data.updates.iter().map(|u| u.1.clone()).collect();
+    let game: Vec<(Vec<Vec<u8>>, ClientId)> = match_data
+        .updates
+        .iter()
+        .map(|u| (u.1.clone(), u.2))
+        .collect();
+    assert_eq!(vec![vec![vec![1u8]]], lobby);
+    assert_eq!(vec![(vec![vec![3u8]], id)], game);
+    Ok(())
+}
+
+#[test]
+fn rooms_should_share_limit_of_clients() -> Result<(), Exception> {
+    let network = victorem::MemoryNetwork::new();
+    let with_master = victorem::GameServerBuilder::new(0)
+        .transport(network.bind("10.0.29.9:1000")?)
+        .master("10.0.29.10:1000".parse().unwrap())
+        .build_rooms::<GameMock>();
+    assert!(matches!(
+        with_master,
+        Err(Exception::IoError(ref e)) if e.kind() == std::io::ErrorKind::InvalidInput
+    ));
+    let server_transport = network.bind("10.0.29.1:1000")?;
+    let mut first = victorem::ClientSocketBuilder::new(0)
+        .transport(network.bind("10.0.29.2:2000")?)
+        .build::<Vec<u8>, Vec<u8>>("10.0.29.1:1000")?;
+    let mut second = victorem::ClientSocketBuilder::new(0)
+        .transport(network.bind("10.0.29.3:2000")?)
+        .build::<Vec<u8>, Vec<u8>>("10.0.29.1:1000")?;
+    let mut lobby_data = GameData::with_draw(vec![1]);
+    let mut match_data = GameData::with_draw(vec![2]);
+    let mut server = victorem::GameServerBuilder::new(0)
+        .transport(server_transport)
+        .max_clients(1)
+        .build_rooms::<GameMock>()?;
+    server.create_room(1, GameMock::new(&mut lobby_data, 100000));
+    server.create_room(2, GameMock::new(&mut match_data, 100000));
+    server.set_lobby(Some(1));
+    first.send(vec![1])?;
+    let timer = std::time::Instant::now();
+    let id = loop {
+        server.run_once();
+        if let Some((_, _, id)) = server.game(1).unwrap().data.updates.first() {
+            break *id;
+        }
+        assert!(timer.elapsed() < Duration::from_secs(5));
+    };
+    assert!(server.move_client(id, 2));
+    second.send(vec![2])?;
+    let reason = loop {
+        server.run_once();
+        if let Err(Exception::Disconnected(reason)) = second.recv() {
+            break reason;
+        }
+        assert!(timer.elapsed() < Duration::from_secs(5));
+    };
+    assert_eq!(victorem::SERVER_FULL, reason);
+    while first.recv().ok() != Some(vec![2u8]) {
+        server.run_once();
+        assert!(timer.elapsed() < Duration::from_secs(5));
+    }
+    Ok(())
+}
+
+#[test]
+fn client_view_should_rewind_to_state_client_saw() -> Result<(), Exception> {
+    let network = victorem::MemoryNetwork::new();
+    let clock = victorem::ManualClock::default();
+    let server_transport = network.bind("10.0.25.1:1000")?;
+    let mut client = victorem::ClientSocketBuilder::new(0)
+        .transport(network.bind("10.0.25.2:2000")?)
+        .clock(clock.clone())
+        .build::<Vec<u8>, Vec<u8>>("10.0.25.1:1000")?;
+    let mut game_data = GameData::with_draw(vec![7]);
+    let game_mock = GameMock::new(&mut game_data, 100000);
+    let mut game_server = victorem::GameServerBuilder::new(0)
+        .transport(server_transport)
+        .clock(clock.clone())
+        .build(game_mock)?;
+    client.send(vec![1])?;
+    let timer = std::time::Instant::now();
+    while client.recv().is_err() {
+        game_server.run_once();
+        clock.advance(Duration::from_millis(10));
+        assert!(timer.elapsed() < Duration::from_secs(5));
+    }
+    //Client reacted to first state so what it saw is older than history.
+    client.send(vec![2])?;
+    game_server.run_once();
+    for _ in 0..30 {
+        clock.advance(Duration::from_millis(10));
+        game_server.run_once();
+    }
+    client.send(vec![3])?;
+    game_server.run_once();
+    game_server.close();
+    drop(game_server);
+    assert_eq!(3, game_data.views.len());
+    assert_eq!(None, game_data.views[1].1);
+    let (time, snapshot) = game_data.views[2];
+    assert!(snapshot.unwrap() <= time);
+    Ok(())
+}
+
+#[test]
+fn sharded_server_should_serve_clients_from_worker_threads() -> Result<(), Exception> {
+    let network = victorem::MemoryNetwork::new();
+    let server_transport = network.bind("10.0.8.1:1000")?;
+    let mut clients = Vec::new();
+    for i in 0..6u8 {
+        let mut client = victorem::ClientSocketBuilder::new(0)
+            .transport(network.bind(("10.0.8.2", 2000 + u16::from(i)))?)
+            .build::<Vec<u8>, Vec<u8>>("10.0.8.1:1000")?;
+        client.send(vec![i])?;
+        clients.push(client);
+    }
+    let mut game_data = GameData::with_draw(vec![7]);
+    let game_mock = GameMock::new(&mut game_data, 100000);
+    let mut game_server = victorem::GameServerBuilder::new(0)
+        .transport(server_transport)
+        .build_sharded(game_mock, 4)?;
+    let handle = game_server.shutdown_handle();
+    std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(300));
+        handle.shutdown("restart");
+    });
+    let summary = game_server.run();
+    assert_eq!(6, summary.peers);
+    assert_eq!(6, summary.metrics.packets_in);
+    drop(game_server);
+    let mut commands: Vec<Vec<u8>> = game_data
+        .updates
+        .iter()
+        .flat_map(|(_, c, _)| c.clone())
+        .collect();
+    commands.sort();
+    assert_eq!((0..6u8).map(|i| vec![i]).collect::<Vec<_>>(), commands);
+    for client in &mut clients {
+        let mut states = 0;
+        let reason = loop {
+            match client.recv() {
+                Err(Exception::Disconnected(reason)) => break reason,
+                Ok(_) => states += 1,
+                Err(e) => panic!("{}", e),
+            }
+        };
+        assert!(states > 0);
+        assert_eq!("restart", reason);
+    }
+    Ok(())
+}
+
+#[test]
+fn sharded_server_should_follow_manual_clock() -> Result<(), Exception> {
+    let network = victorem::MemoryNetwork::new();
+    let clock = victorem::ManualClock::default();
+    let server_transport = network.bind("10.0.28.1:1000")?;
+    let mut client = victorem::ClientSocketBuilder::new(0)
+        .transport(network.bind("10.0.28.2:2000")?)
+        .build::<Vec<u8>, Vec<u8>>("10.0.28.1:1000")?;
+    let mut game_data = GameData::with_draw(vec![7]);
+    let game_mock = GameMock::new(&mut game_data, 100000);
+    let mut game_server = victorem::GameServerBuilder::new(0)
+        .transport(server_transport)
+        .clock(clock.clone())
+        .idle_timeout(Some(Duration::from_millis(100)))
+        .build_sharded(game_mock, 2)?;
+    client.send(vec![1])?;
+    let timer = std::time::Instant::now();
+    let mut advanced = Duration::from_millis(0);
+    while client.recv().is_err() {
+        game_server.run_once();
+        clock.advance(Duration::from_millis(10));
+        advanced += Duration::from_millis(10);
+        std::thread::sleep(Duration::from_millis(1));
+        assert!(timer.elapsed() < Duration::from_secs(5));
+    }
+    //Idle workers don't move time so only test expires session.
+    std::thread::sleep(Duration::from_millis(50));
+    game_server.run_once();
+    assert_eq!(1, game_server.metrics().peers);
+    while game_server.metrics().peers > 0 {
+        game_server.run_once();
+        clock.advance(Duration::from_millis(20));
+        advanced += Duration::from_millis(20);
+        std::thread::sleep(Duration::from_millis(1));
+        assert!(timer.elapsed() < Duration::from_secs(5));
+    }
+    assert_eq!(advanced, victorem::Clock::now(&clock));
+    Ok(())
+}
+
+//Transport of client behind NAT that changes its port.
+struct Rebinding(std::sync::Arc<std::sync::Mutex<victorem::MemoryTransport>>);
+
+impl victorem::Transport for Rebinding {
+    fn send_to(&self, buf: &[u8], addr: &SocketAddr) -> std::io::Result<usize> {
+        self.0.lock().unwrap().send_to(buf, addr)
+    }
+
+    fn recv_from(&self, buf: &mut [u8]) -> std::io::Result<(usize, SocketAddr)> {
+        self.0.lock().unwrap().recv_from(buf)
+    }
+
+    fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.0.lock().unwrap().local_addr()
+    }
+}
+
+#[test]
+fn server_should_keep_id_of_client_from_new_address() -> Result<(), Exception> {
+    let network = victorem::MemoryNetwork::new();
+    let server_transport = network.bind("10.0.9.1:1000")?;
+    let transport = std::sync::Arc::new(std::sync::Mutex::new(network.bind("10.0.9.2:2000")?));
+    let rebinding = Rebinding(transport.clone());
+    let client = std::thread::spawn(move || {
+        let mut client = victorem::ClientSocketBuilder::new(0)
+            .transport(rebinding)
+            .build::<Vec<u8>, Vec<u8>>("10.0.9.1:1000")?;
+        client.send(vec![1])?;
+        std::thread::sleep(Duration::from_millis(100));
+        while client.recv().is_ok() {}
+        *transport.lock().unwrap() = network.bind("10.0.9.3:3000")?;
+        client.send(vec![2])?;
+        let timer = std::time::Instant::now();
+        while client.recv().is_err() {
+            assert!(timer.elapsed() < Duration::from_secs(5));
+        }
+        Ok::<ClientSocket<Vec<u8>, Vec<u8>, _, _>, Exception>(client)
+    });
+    let mut game_data = GameData::with_draw(vec![7]);
+    let game_mock = GameMock::new(&mut game_data, 100000);
+    let mut game_server = victorem::GameServerBuilder::new(0)
+        .transport(server_transport)
+        .build(game_mock)?;
+    let handle = game_server.shutdown_handle();
+    std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(400));
+        handle.shutdown("done");
+    });
+    let summary = game_server.run();
+    drop(game_server);
+    let _client = client.join().unwrap()?;
+    assert_eq!(1, summary.peers);
+    let updates: Vec<(Vec<Vec<u8>>, ClientId)> = game_data
+        .updates
+        .iter()
+        .map(|(_, c, id)| (c.clone(), *id))
+        .collect();
+    assert_eq!(vec![(vec![vec![1u8]], 1), (vec![vec![2u8]], 1)], updates);
+    Ok(())
+}
+
+#[test]
+fn server_should_resume_session_of_client_in_grace_period() -> Result<(), Exception> {
+    let network = victorem::MemoryNetwork::new();
+    let clock = victorem::ManualClock::default();
+    let server_transport = network.bind("10.0.10.1:1000")?;
+    let mut first = victorem::ClientSocketBuilder::new(0)
+        .transport(network.bind("10.0.10.2:2000")?)
+        .clock(clock.clone())
+        .build::<Vec<u8>, Vec<u8>>("10.0.10.1:1000")?;
+    let mut game_data = GameData::with_draw(vec![7]);
+    let game_mock = GameMock::new(&mut game_data, 100000);
+    let mut game_server = victorem::GameServerBuilder::new(0)
+        .transport(server_transport)
+        .clock(clock.clone())
+        .idle_timeout(Some(Duration::from_millis(100)))
+        .grace_period(Duration::from_secs(5))
+        .build(game_mock)?;
+    first.send(vec![1])?;
+    let timer = std::time::Instant::now();
+    while first.recv().is_err() {
+        game_server.run_once();
+        clock.advance(Duration::from_millis(20));
+        assert!(timer.elapsed() < Duration::from_secs(5));
+    }
+    let token = first.into_resume_token().unwrap();
+    while game_server.metrics().peers > 0 {
+        game_server.run_once();
+        clock.advance(Duration::from_millis(20));
+        assert!(timer.elapsed() < Duration::from_secs(5));
+    }
+    let mut second = victorem::ClientSocketBuilder::new(0)
+        .transport(network.bind("10.0.10.3:2000")?)
+        .clock(clock.clone())
+        .resume(token)
+        .build::<Vec<u8>, Vec<u8>>("10.0.10.1:1000")?;
+    second.send(vec![2])?;
+    while second.recv().is_err() {
+        game_server.run_once();
+        clock.advance(Duration::from_millis(20));
+        assert!(timer.elapsed() < Duration::from_secs(5));
+    }
+    game_server.close();
+    drop(game_server);
+    let updates: Vec<(Vec<Vec<u8>>, ClientId)> = game_data
+        .updates
+        .iter()
+        .map(|(_, c, id)| (c.clone(), *id))
+        .collect();
+    assert_eq!(vec![(vec![vec![1u8]], 1), (vec![vec![2u8]], 1)], updates);
+    assert!(game_data
+        .events
+        .iter()
+        .any(|e| matches!(e, ServerEvent::ClientReconnected(1))));
+    assert!(!game_data
+        .events
+        .iter()
+        .any(|e| matches!(e, ServerEvent::ClientDisconnected(_))));
+    Ok(())
+}
+
+//Transport behind NAT that drops datagrams from peers it never sent datagram to.
+struct Nat {
+    transport: victorem::MemoryTransport,
+    opened: std::sync::Mutex<std::collections::HashSet<SocketAddr>>,
+}
+
+impl Nat {
+    fn new(transport: victorem::MemoryTransport) -> Nat {
+        Nat {
+            transport,
+            opened: std::sync::Mutex::new(std::collections::HashSet::new()),
+        }
+    }
+}
+
+impl victorem::Transport for Nat {
+    fn send_to(&self, buf: &[u8], addr: &SocketAddr) -> std::io::Result<usize> {
+        self.opened.lock().unwrap().insert(*addr);
+        self.transport.send_to(buf, addr)
+    }
+
+    fn recv_from(&self, buf: &mut [u8]) -> std::io::Result<(usize, SocketAddr)> {
+        loop {
+            let (size, from) = self.transport.recv_from(buf)?;
+            if self.opened.lock().unwrap().contains(&from) {
+                return Ok((size, from));
+            }
+        }
+    }
+
+    fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.transport.local_addr()
+    }
+}
+
+#[test]
+fn client_should_reach_server_behind_nat_through_rendezvous() -> Result<(), Exception> {
+    let network = victorem::MemoryNetwork::new();
+    let rendezvous_address: SocketAddr = "10.0.11.1:1000".parse().unwrap();
+    let mut rendezvous = victorem::GameServerBuilder::new(0)
+        .transport(network.bind(rendezvous_address)?)
+        .build_rendezvous()?;
+    let rendezvous_handle = rendezvous.shutdown_handle();
+    let rendezvous = std::thread::spawn(move || {
+        rendezvous.run();
+        rendezvous.servers()
+    });
+    let mut game_data = GameData::with_draw(vec![7]);
+    let game_mock = GameMock::new(&mut game_data, 100000);
+    let mut game_server = victorem::GameServerBuilder::new(0)
+        .transport(Nat::new(network.bind("10.0.11.2:2000")?))
+        .rendezvous(rendezvous_address, "home")
+        .build(game_mock)?;
+    let handle = game_server.shutdown_handle();
+    let client_transport = Nat::new(network.bind("10.0.11.3:3000")?);
+    let client = std::thread::spawn(move || {
+        let state = (|| {
+            let mut client = victorem::ClientSocketBuilder::new(0)
+                .transport(client_transport)
+                .build_rendezvous::<Vec<u8>, Vec<u8>>(rendezvous_address, "home")?;
+            let timer = std::time::Instant::now();
+            while timer.elapsed() < Duration::from_secs(5) {
+                let _ = client.send(vec![1]);
+                if let Ok(state) = client.recv() {
+                    return Ok(state);
+                }
+            }
+            Err(Exception::NotValidIdError)
+        })();
+        handle.shutdown("done");
+        state
+    });
+    game_server.run();
+    drop(game_server);
+    let state = client.join().unwrap();
+    rendezvous_handle.shutdown("done");
+    assert_eq!(vec!["home".to_owned()], rendezvous.join().unwrap());
+    assert_eq!(vec![7u8], state?);
+    assert!(game_data.updates.iter().any(|(_, c, _)| c.contains(&vec![1u8])));
+    Ok(())
+}
+
+#[test]
+fn listen_server_should_play_local_client_without_network() -> Result<(), Exception> {
+    let network = victorem::MemoryNetwork::new();
+    let server_transport = network.bind("10.0.12.1:1000")?;
+    let mut remote = victorem::ClientSocketBuilder::new(0)
+        .transport(network.bind("10.0.12.2:2000")?)
+        .build::<Vec<u8>, Vec<u8>>("10.0.12.1:1000")?;
+    let mut game_data = GameData::with_draw(vec![7]);
+    let game_mock = GameMock::new(&mut game_data, 100000);
+    let mut game_server = victorem::GameServerBuilder::new(0)
+        .transport(server_transport)
+        .build(game_mock)?;
+    let mut local = game_server.connect_local();
+    let mut second = game_server.connect_local();
+    assert_eq!((1, 2), (local.id(), second.id()));
+    local.send(vec![1])?;
+    remote.send(vec![2])?;
+    let timer = std::time::Instant::now();
+    let state = loop {
+        assert!(game_server.run_once());
+        match local.recv() {
+            Ok(state) => break state,
+            Err(Exception::IoError(ref e)) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                assert!(timer.elapsed() < Duration::from_secs(5))
+            }
+            Err(e) => panic!("{}", e),
+        }
+    };
+    assert_eq!(vec![7u8], state);
+    local.disconnect("bye");
+    game_server.run_once();
+    let summary = game_server.close();
+    assert_eq!(2, summary.peers);
+    let reason = loop {
+        match second.recv() {
+            Err(Exception::Disconnected(reason)) => break reason,
+            Ok(state) => assert_eq!(vec![7u8], state),
+            Err(e) => panic!("{}", e),
+        }
+    };
+    assert_eq!("Server stopped", reason);
+    assert!(second.send(vec![3]).is_err());
+    drop(game_server);
+    let mut commands: Vec<(Vec<Vec<u8>>, ClientId)> = game_data
+        .updates
+        .iter()
+        .map(|(_, c, from)| (c.clone(), *from))
+        .collect();
+    commands.sort_by_key(|(_, from)| *from);
+    assert_eq!(vec![(vec![vec![1u8]], 1), (vec![vec![2u8]], 3)], commands);
+    assert!(game_data
+        .events
+        .iter()
+        .any(|e| matches!(e, ServerEvent::ClientDisconnected((1, reason)) if reason == "bye")));
+    Ok(())
+}
+
+#[test]
+fn client_should_discover_servers_in_lan() -> Result<(), Exception> {
+    let network = victorem::MemoryNetwork::new();
+    let mut handles = Vec::new();
+    let servers = [
+        ("10.0.13.1:1000", Some("first")),
+        ("10.0.13.2:1000", Some("second")),
+        ("10.0.13.3:1000", None),
+    ];
+    for (address, name) in servers {
+        let transport = network.bind(address)?;
+        let (sender, receiver) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            let mut game_data = GameData::new();
+            game_data.new_client = None;
+            game_data.info = name.map(|name| ServerInfo {
+                name: name.to_owned(),
+                players: 2,
+                map: "de_dust".to_owned(),
+            });
+            let game_mock = GameMock::new(&mut game_data, 100000);
+            let mut game_server = victorem::GameServerBuilder::new(0)
+                .transport(transport)
+                .build(game_mock)
+                .unwrap();
+            sender.send(game_server.shutdown_handle()).unwrap();
+            game_server.run();
+        });
+        handles.push(receiver.recv().unwrap());
+    }
+    std::thread::sleep(Duration::from_millis(100));
+    let servers = victorem::ClientSocketBuilder::new(0)
+        .transport(network.bind("10.0.13.9:2000")?)
+        .discover("255.255.255.255:1000", Duration::from_millis(200))?;
+    for handle in handles {
+        handle.shutdown("done");
+    }
+    let mut names: Vec<(String, SocketAddr)> = servers
+        .iter()
+        .map(|s| (s.info.name.clone(), s.address))
+        .collect();
+    names.sort();
+    assert_eq!(
+        vec![
+            ("first".to_owned(), "10.0.13.1:1000".parse().unwrap()),
+            ("second".to_owned(), "10.0.13.2:1000".parse().unwrap()),
+        ],
+        names
+    );
+    assert!(servers.iter().all(|s| s.info.players == 2 && s.ping < Duration::from_millis(200)));
+    Ok(())
+}
+
+#[test]
+fn server_should_answer_limited_queries_without_session() -> Result<(), Exception> {
+    let network = victorem::MemoryNetwork::new();
+    //Time of server stands still while queries are sent so they are all in one period.
+    let clock = victorem::ManualClock::default();
+    let server_clock = clock.clone();
+    let transport = network.bind("10.0.14.1:1000")?;
+    let mut client = victorem::ClientSocketBuilder::new(0)
+        .transport(network.bind("10.0.14.2:2000")?)
+        .build::<Vec<u8>, Vec<u8>>("10.0.14.1:1000")?;
+    client.send(vec![1])?;
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let server = std::thread::spawn(move || {
+        let mut game_data = GameData::with_draw(vec![7]);
+        let mut values = std::collections::BTreeMap::new();
+        values.insert("map".to_owned(), "de_dust".to_owned());
+        game_data.status = Some(ServerStatus {
+            players: 0,
+            max_players: 8,
+            version: "1.2".to_owned(),
+            values,
+        });
+        let game_mock = GameMock::new(&mut game_data, 100000);
+        let mut game_server = victorem::GameServerBuilder::new(0)
+            .transport(transport)
+            .clock(server_clock)
+            .build(game_mock)
+            .unwrap();
+        sender.send(game_server.shutdown_handle()).unwrap();
+        game_server.run()
+    });
+    let handle = receiver.recv().unwrap();
+    let timer = std::time::Instant::now();
+    while client.recv().is_err() {
+        clock.advance(Duration::from_millis(20));
+        std::thread::sleep(Duration::from_millis(1));
+        assert!(timer.elapsed() < Duration::from_secs(5));
+    }
+    let query = |timeout| {
+        victorem::ClientSocketBuilder::new(0)
+            .transport(network.bind("10.0.14.3:3000").unwrap())
+            .query_server("10.0.14.1:1000", timeout)
+    };
+    let status = query(Duration::from_millis(500))?;
+    assert_eq!(1, status.players);
+    assert_eq!(8, status.max_players);
+    assert_eq!("1.2", status.version);
+    assert_eq!(Some("de_dust"), status.values.get("map").map(|m| m.as_str()));
+    let mut answered = 1;
+    while query(Duration::from_millis(200)).is_ok() {
+        answered += 1;
+    }
+    assert_eq!(10, answered);
+    handle.shutdown("done");
+    let summary = server.join().unwrap();
+    assert_eq!(1, summary.peers);
+    Ok(())
+}
+
+#[test]
+fn master_server_should_list_filtered_servers_with_heartbeat() -> Result<(), Exception> {
+    let network = victorem::MemoryNetwork::new();
+    let manual = victorem::ManualClock::default();
+    let master_address: SocketAddr = "10.0.15.1:1000".parse().unwrap();
+    let mut master = victorem::GameServerBuilder::new(0)
+        .transport(network.bind(master_address)?)
+        .clock(manual.clone())
+        .build_master()?;
+    let mut handles = Vec::new();
+    let servers = [
+        ("10.0.15.2:1000", "1.0", "de_dust"),
+        ("10.0.15.3:1000", "1.0", "cs_office"),
+        ("10.0.15.4:1000", "0.9", "de_dust"),
+    ];
+    for (address, version, map) in servers {
+        let transport = network.bind(address)?;
+        let (sender, receiver) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            let mut game_data = GameData::new();
+            game_data.new_client = None;
+            let mut status = ServerStatus {
+                version: version.to_owned(),
+                ..Default::default()
+            };
+            status.values.insert("map".to_owned(), map.to_owned());
+            game_data.status = Some(status);
+            let game_mock = GameMock::new(&mut game_data, 100000);
+            let mut game_server = victorem::GameServerBuilder::new(0)
+                .transport(transport)
+                .master(master_address)
+                .build(game_mock)
+                .unwrap();
+            sender.send(game_server.shutdown_handle()).unwrap();
+            game_server.run();
+        });
+        handles.push(receiver.recv().unwrap());
+    }
+    let timer = std::time::Instant::now();
+    while master.servers().len() < 3 {
+        if !master.run_once() {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert!(timer.elapsed() < Duration::from_secs(5));
+    }
+    let client_transport = network.bind("10.0.15.9:2000")?;
+    let client = std::thread::spawn(move || {
+        let mut filter = victorem::ServerFilter {
+            version: Some("1.0".to_owned()),
+            ..Default::default()
+        };
+        filter.values.insert("map".to_owned(), "de_dust".to_owned());
+        victorem::ClientSocketBuilder::new(0)
+            .transport(client_transport)
+            .list_servers(master_address, filter, Duration::from_secs(1))
+    });
+    while !client.is_finished() {
+        master.run_once();
+        std::thread::sleep(Duration::from_millis(1));
+        assert!(timer.elapsed() < Duration::from_secs(5));
+    }
+    let listed = client.join().unwrap()?;
+    assert_eq!(1, listed.len());
+    assert_eq!("10.0.15.2:1000".parse::<SocketAddr>().unwrap(), listed[0].address);
+    assert_eq!("1.0", listed[0].status.version);
+    handles.remove(0).shutdown("done");
+    std::thread::sleep(Duration::from_millis(100));
+    master.run_once();
+    manual.advance(Duration::from_secs(6));
+    let alive = loop {
+        master.run_once();
+        let mut alive: Vec<SocketAddr> = master.servers().iter().map(|s| s.address).collect();
+        if alive.len() == 2 {
+            alive.sort();
+            break alive;
+        }
+        std::thread::sleep(Duration::from_millis(1));
+        assert!(timer.elapsed() < Duration::from_secs(5));
+    };
+    let expected: Vec<SocketAddr> = vec![
+        "10.0.15.3:1000".parse().unwrap(),
+        "10.0.15.4:1000".parse().unwrap(),
+    ];
+    assert_eq!(expected, alive);
+    for handle in handles {
+        handle.shutdown("done");
+    }
+    Ok(())
+}
+
+#[test]
+fn server_should_reject_client_with_bad_credentials() -> Result<(), Exception> {
+    let network = victorem::MemoryNetwork::new();
+    let server_transport = network.bind("10.0.16.1:1000")?;
+    let mut good = victorem::ClientSocketBuilder::new(0)
+        .transport(network.bind("10.0.16.2:2000")?)
+        .auth("secret")
+        .build::<Vec<u8>, Vec<u8>>("10.0.16.1:1000")?;
+    let mut bad = victorem::ClientSocketBuilder::new(0)
+        .transport(network.bind("10.0.16.3:2000")?)
+        .auth("wrong")
+        .build::<Vec<u8>, Vec<u8>>("10.0.16.1:1000")?;
+    good.send(vec![1])?;
+    bad.send(vec![2])?;
+    let mut game_data = GameData::with_draw(vec![7]);
+    game_data.password = Some(b"secret".to_vec());
+    let game_mock = GameMock::new(&mut game_data, 100000);
+    let mut game_server = victorem::GameServerBuilder::new(0)
+        .transport(server_transport)
+        .build(game_mock)?;
+    let timer = std::time::Instant::now();
+    let reason = loop {
+        game_server.run_once();
+        match bad.recv() {
+            Err(Exception::Disconnected(reason)) => break reason,
+            Ok(_) => panic!("state sent to rejected client"),
+            Err(_) => assert!(timer.elapsed() < Duration::from_secs(5)),
+        }
+    };
+    assert_eq!("Bad password", reason);
+    while good.recv().is_err() {
+        game_server.run_once();
+        assert!(timer.elapsed() < Duration::from_secs(5));
+    }
+    good.send(vec![3])?;
+    for _ in 0..10 {
+        game_server.run_once();
+    }
+    let summary = game_server.close();
+    assert_eq!(1, summary.peers);
+    let users: Vec<_> = game_data.users.iter().map(|(_, u)| u.clone()).collect();
+    assert!(!users.is_empty());
+    assert!(users.iter().all(|u| u.as_deref() == Some("player 1")));
+    Ok(())
+}
+
+#[test]
+fn kicked_and_not_allowed_clients_should_get_reason() -> Result<(), Exception> {
+    let network = victorem::MemoryNetwork::new();
+    let server_transport = network.bind("10.0.17.1:1000")?;
+    let mut player = victorem::ClientSocketBuilder::new(0)
+        .transport(network.bind("10.0.17.2:2000")?)
+        .build::<Vec<u8>, Vec<u8>>("10.0.17.1:1000")?;
+    let mut banned = victorem::ClientSocketBuilder::new(0)
+        .transport(network.bind("10.0.17.3:2000")?)
+        .build::<Vec<u8>, Vec<u8>>("10.0.17.1:1000")?;
+    let mut game_data = GameData::with_draw(vec![7]);
+    game_data.banned = Some("10.0.17.3:2000".parse().unwrap());
+    let game_mock = GameMock::new(&mut game_data, 100000);
+    let mut game_server = victorem::GameServerBuilder::new(0)
+        .transport(server_transport)
+        .build(game_mock)?;
+    let mut local = game_server.connect_local();
+    //Ids are given in order so player connected after local one gets next id.
+    let id = local.id() + 1;
+    player.send(vec![1])?;
+    let timer = std::time::Instant::now();
+    while player.recv().is_err() {
+        game_server.run_once();
+        assert!(timer.elapsed() < Duration::from_secs(5));
+    }
+    banned.send(vec![2])?;
+    let reason = loop {
+        game_server.run_once();
+        if let Err(Exception::Disconnected(reason)) = banned.recv() {
+            break reason;
+        }
+        assert!(timer.elapsed() < Duration::from_secs(5));
+    };
+    assert_eq!(victorem::NOT_ALLOWED, reason);
+    game_server.kick(id, "Cheating");
+    game_server.kick(local.id(), "Away from keyboard");
+    let reason = loop {
+        match player.recv() {
+            Err(Exception::Disconnected(reason)) => break reason,
+            _ => game_server.run_once(),
+        };
+        assert!(timer.elapsed() < Duration::from_secs(5));
+    };
+    assert_eq!("Cheating", reason);
+    let reason = loop {
+        if let Err(Exception::Disconnected(reason)) = local.recv() {
+            break reason;
+        }
+        assert!(timer.elapsed() < Duration::from_secs(5));
+    };
+    assert_eq!("Away from keyboard", reason);
+    assert_eq!(0, game_server.close().peers);
+    let local_id = local.id();
+    drop(game_server);
+    let disconnected: Vec<(ClientId, String)> = game_data
+        .events
+        .iter()
+        .filter_map(|e| match e {
+            ServerEvent::ClientDisconnected(d) => Some(d.clone()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        vec![
+            (id, "Cheating".to_owned()),
+            (local_id, "Away from keyboard".to_owned())
+        ],
+        disconnected
+    );
+    Ok(())
+}
+
+#[test]
+fn kicking_unknown_client_should_not_report_disconnect() -> Result<(), Exception> {
+    let network = victorem::MemoryNetwork::new();
+    let server_transport = network.bind("10.0.26.1:1000")?;
+    let mut game_data = GameData::with_draw(vec![7]);
+    let game_mock = GameMock::new(&mut game_data, 100000);
+    let mut game_server = victorem::GameServerBuilder::new(0)
+        .transport(server_transport)
+        .build(game_mock)?;
+    let local = game_server.connect_local();
+    game_server.kick(42, "Cheating");
+    game_server.kick(local.id(), "Away from keyboard");
+    game_server.kick(local.id(), "Away from keyboard");
+    game_server.run_once();
+    let local_id = local.id();
+    drop(game_server);
+    let disconnected: Vec<(ClientId, String)> = game_data
+        .events
+        .iter()
+        .filter_map(|e| match e {
+            ServerEvent::ClientDisconnected(d) => Some(d.clone()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(vec![(local_id, "Away from keyboard".to_owned())], disconnected);
+    Ok(())
+}
+
+//Subscriber remembering events with name of span they happened in.
+#[cfg(feature = "tracing")]
+#[derive(Default)]
+struct Recorder {
+    spans: std::sync::Mutex<Vec<&'static str>>,
+    stack: std::sync::Mutex<Vec<&'static str>>,
+    events: std::sync::Arc<std::sync::Mutex<Vec<String>>>,
+}
+
+#[cfg(feature = "tracing")]
+struct Message(String);
+
+#[cfg(feature = "tracing")]
+impl tracing::field::Visit for Message {
+    fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            self.0 = format!("{:?}", value);
+        }
+    }
+}
+
+#[cfg(feature = "tracing")]
+impl tracing::Subscriber for Recorder {
+    fn enabled(&self, _: &tracing::Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, span: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+        let mut spans = self.spans.lock().unwrap();
+        spans.push(span.metadata().name());
+        tracing::span::Id::from_u64(spans.len() as u64)
+    }
+
+    fn record(&self, _: &tracing::span::Id, _: &tracing::span::Record<'_>) {}
+
+    fn record_follows_from(&self, _: &tracing::span::Id, _: &tracing::span::Id) {}
+
+    fn event(&self, event: &tracing::Event<'_>) {
+        let mut message = Message(String::new());
+        event.record(&mut message);
+        let stack = self.stack.lock().unwrap();
+        let span = stack.last().copied().unwrap_or_default();
+        self.events
+            .lock()
+            .unwrap()
+            .push(format!("{}: {}", span, message.0));
+    }
+
+    fn enter(&self, span: &tracing::span::Id) {
+        let name = self.spans.lock().unwrap()[span.into_u64() as usize - 1];
+        self.stack.lock().unwrap().push(name);
+    }
+
+    fn exit(&self, _: &tracing::span::Id) {
+        self.stack.lock().unwrap().pop();
+    }
+}
+
+#[cfg(feature = "tracing")]
+#[test]
+fn connect_and_disconnect_should_be_traced() -> Result<(), Exception> {
+    let recorder = Recorder::default();
+    let events = recorder.events.clone();
+    let network = victorem::MemoryNetwork::new();
+    let server_transport = network.bind("10.0.27.1:1000")?;
+    let mut client = victorem::ClientSocketBuilder::new(0)
+        .transport(network.bind("10.0.27.2:2000")?)
+        .build::<Vec<u8>, Vec<u8>>("10.0.27.1:1000")?;
+    let mut game_data = GameData::with_draw(vec![7]);
+    let game_mock = GameMock::new(&mut game_data, 100000);
+    let mut game_server = victorem::GameServerBuilder::new(0)
+        .transport(server_transport)
+        .build(game_mock)?;
+    tracing::subscriber::with_default(recorder, || {
+        client.send(vec![1])?;
+        let timer = std::time::Instant::now();
+        while client.recv().is_err() {
+            game_server.run_once();
+            assert!(timer.elapsed() < Duration::from_secs(5));
+        }
+        game_server.kick(1, "Cheating");
+        Ok::<_, Exception>(())
+    })?;
+    let events = events.lock().unwrap();
+    assert!(events.iter().any(|e| e == "peer: peer added"), "{:?}", events);
+    assert!(events.iter().any(|e| e == ": peer kicked"), "{:?}", events);
+    assert!(events.iter().any(|e| e == ": peer disconnected"), "{:?}", events);
+    Ok(())
+}
+
+#[test]
+fn kicked_suspended_client_should_not_resume_session() -> Result<(), Exception> {
+    let network = victorem::MemoryNetwork::new();
+    let clock = victorem::ManualClock::default();
+    let server_transport = network.bind("10.0.24.1:1000")?;
+    let mut first = victorem::ClientSocketBuilder::new(0)
+        .transport(network.bind("10.0.24.2:2000")?)
+        .clock(clock.clone())
+        .build::<Vec<u8>, Vec<u8>>("10.0.24.1:1000")?;
+    let mut game_data = GameData::with_draw(vec![7]);
+    let game_mock = GameMock::new(&mut game_data, 100000);
+    let mut game_server = victorem::GameServerBuilder::new(0)
+        .transport(server_transport)
+        .clock(clock.clone())
+        .idle_timeout(Some(Duration::from_millis(100)))
+        .grace_period(Duration::from_secs(5))
+        .build(game_mock)?;
+    first.send(vec![1])?;
+    let timer = std::time::Instant::now();
+    while first.recv().is_err() {
+        game_server.run_once();
+        clock.advance(Duration::from_millis(20));
+        assert!(timer.elapsed() < Duration::from_secs(5));
+    }
+    let token = first.into_resume_token().unwrap();
+    while game_server.metrics().peers > 0 {
+        game_server.run_once();
+        clock.advance(Duration::from_millis(20));
+        assert!(timer.elapsed() < Duration::from_secs(5));
+    }
+    game_server.kick(1, "Cheating");
+    let mut second = victorem::ClientSocketBuilder::new(0)
+        .transport(network.bind("10.0.24.3:2000")?)
+        .clock(clock.clone())
+        .resume(token)
+        .build::<Vec<u8>, Vec<u8>>("10.0.24.1:1000")?;
+    second.send(vec![2])?;
+    while second.recv().is_err() {
+        game_server.run_once();
+        clock.advance(Duration::from_millis(20));
+        assert!(timer.elapsed() < Duration::from_secs(5));
+    }
+    game_server.close();
+    drop(game_server);
+    let updates: Vec<(Vec<Vec<u8>>, ClientId)> = game_data
+        .updates
+        .iter()
+        .map(|(_, c, id)| (c.clone(), *id))
+        .collect();
+    assert_eq!(vec![(vec![vec![1u8]], 1), (vec![vec![2u8]], 2)], updates);
+    assert!(!game_data
+        .events
+        .iter()
+        .any(|e| matches!(e, ServerEvent::ClientReconnected(_))));
+    assert!(game_data
+        .events
+        .iter()
+        .any(|e| matches!(e, ServerEvent::ClientDisconnected((1, r)) if r == "Cheating")));
+    Ok(())
+}
+
+#[test]
+fn full_server_should_queue_and_refuse_clients_over_limit() -> Result<(), Exception> {
+    let network = victorem::MemoryNetwork::new();
+    let server_transport = network.bind("10.0.18.1:1000")?;
+    let mut clients = Vec::new();
+    for i in 2..5 {
+        let client = victorem::ClientSocketBuilder::new(0)
+            .transport(network.bind(format!("10.0.18.{}:2000", i))?)
+            .build::<Vec<u8>, Vec<u8>>("10.0.18.1:1000")?;
+        clients.push(client);
+    }
+    let mut game_data = GameData::with_draw(vec![7]);
+    let game_mock = GameMock::new(&mut game_data, 100000);
+    let mut game_server = victorem::GameServerBuilder::new(0)
+        .transport(server_transport)
+        .max_clients(1)
+        .wait_queue(1)
+        .build(game_mock)?;
+    //Local client is not limited and gives known id to first remote client.
+    let local = game_server.connect_local();
+    let id = local.id() + 1;
+    clients[0].send(vec![1])?;
+    let timer = std::time::Instant::now();
+    while clients[0].recv().is_err() {
+        game_server.run_once();
+        assert!(timer.elapsed() < Duration::from_secs(5));
+    }
+    clients[1].send(vec![2])?;
+    game_server.run_once();
+    clients[2].send(vec![3])?;
+    let reason = loop {
+        game_server.run_once();
+        if let Err(Exception::Disconnected(reason)) = clients[2].recv() {
+            break reason;
+        }
+        assert!(timer.elapsed() < Duration::from_secs(5));
+    };
+    assert_eq!(victorem::SERVER_FULL, reason);
+    for _ in 0..10 {
+        game_server.run_once();
+    }
+    assert!(clients[1].recv().is_err());
+    game_server.kick(id, "Make place");
+    loop {
+        clients[1].send(vec![2])?;
+        game_server.run_once();
+        if clients[1].recv().is_ok() {
+            break;
+        }
+        assert!(timer.elapsed() < Duration::from_secs(5));
+    }
+    game_server.close();
+    let queued: SocketAddr = "10.0.18.3:2000".parse().unwrap();
+    let refused: SocketAddr = "10.0.18.4:2000".parse().unwrap();
+    assert!(game_data.events.iter().any(|e| match e {
+        ServerEvent::ClientQueued(a) => *a == queued,
+        _ => false,
+    }));
+    assert!(game_data.events.iter().any(|e| match e {
+        ServerEvent::ServerFull(a) => *a == refused,
+        _ => false,
+    }));
+    Ok(())
+}
+
+#[allow(dead_code)]
 trait Middleware<T> {
-    fn execute(&mut self, data: T) -> Result<T, Box<Error>>;
+    fn execute(&mut self, data: T) -> Result<T, Box<dyn Error>>;
     fn next(&mut self) -> &mut Option<Box<dyn Middleware<T>>>;
-    fn run(&mut self, data: T) -> Result<T, Box<Error>> {
+    fn run(&mut self, data: T) -> Result<T, Box<dyn Error>> {
         let data = self.execute(data)?;
         match &mut self.next() {
             Some(next) => next.execute(data),
@@ -192,6 +1970,7 @@ trait Middleware<T> {
     }
 }
 
+#[allow(dead_code)]
 fn compose<T: From<U>, U>(
     rhs: impl FnOnce(T) -> U,
     lhs: impl FnOnce(T) -> U,
@@ -199,14 +1978,17 @@ fn compose<T: From<U>, U>(
     move |x| lhs(rhs(x).into())
 }
 
+#[allow(dead_code)]
 fn curry<T, U, Z>(x: T, f: impl FnOnce(T, U) -> Z) -> impl FnOnce(U) -> Z {
     move |y| f(x, y)
 }
 
+#[allow(dead_code)]
 fn add(x: i32, y: i32) -> i32 {
     x + y
 }
 
+#[allow(dead_code)]
 fn add_static(mut x: i32) -> impl FnMut(i32) -> i32 {
     move |y| {
         x += 10;
@@ -214,16 +1996,17 @@ fn add_static(mut x: i32) -> impl FnMut(i32) -> i32 {
     }
 }
 
+#[allow(dead_code)]
 struct AddOne {
-    next: Option<Box<Middleware<i32>>>,
+    next: Option<Box<dyn Middleware<i32>>>,
 }
 
 impl Middleware<i32> for AddOne {
-    fn execute(&mut self, data: i32) -> Result<i32, Box<Error>> {
+    fn execute(&mut self, data: i32) -> Result<i32, Box<dyn Error>> {
         Ok(data + 1)
     }
 
-    fn next(&mut self) -> &mut Option<Box<Middleware<i32>>> {
+    fn next(&mut self) -> &mut Option<Box<dyn Middleware<i32>>> {
         &mut self.next
     }
 }